@@ -1,11 +1,56 @@
+use std::collections::HashSet;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
 use std::{env, fs};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{CommandFactory, Parser};
 
-use korrect::cli::{generate_completions, Cli, Commands};
+use korrect::cache::{read_cache_entries, read_cache_entry, unix_now, CacheEntry};
+use korrect::cli::{generate_completions, CacheCommands, Cli, Commands, OutputFormat};
+use korrect::fanout::{match_contexts, Fanout, FanoutResult};
+use korrect::output::{format_age, format_size, format_timestamp, print_structured, table};
+use korrect::pins::{read_pins, write_pins, Pin, Pins};
+use korrect::shim::{ConnectionFlags, KorrectShimConfig, Resolution};
+use korrect::store::{ChecksumStatus, KubectlStore};
+use korrect::version::KubeVersion;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Inventory {
+    versions: Vec<InstalledKubectl>,
+    shims: Vec<ShimEntry>,
+    /// Pinned versions by context, installed or not
+    pins: Pins,
+}
+
+#[derive(Serialize)]
+struct InstalledKubectl {
+    version: String,
+    path: PathBuf,
+    /// Bytes on disk
+    size: u64,
+    /// Seconds since the Unix epoch
+    installed_at: Option<u64>,
+    /// Seconds since the Unix epoch
+    last_used: Option<u64>,
+    checksum: ChecksumStatus,
+    /// Contexts whose cached server version resolves to this kubectl
+    contexts: Vec<String>,
+    /// Contexts pinned to this kubectl
+    pinned: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ShimEntry {
+    name: String,
+    /// `shim`, `alias` for symlinks, or `file` for anything else
+    kind: &'static str,
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<PathBuf>,
+}
 
 struct Korrect {
     korrect_config_path: PathBuf,
@@ -90,23 +135,702 @@ impl Korrect {
         Ok(())
     }
 
-    fn list(&self) -> anyhow::Result<()> {
+    fn list(&self, output: OutputFormat) -> anyhow::Result<()> {
         if !self.korrect_bin_path.exists() {
             println!("korrect is not set up. Run 'korrect setup' first.");
             return Ok(());
         }
 
+        let inventory = self.inventory()?;
+        if print_structured(output, &inventory)? {
+            return Ok(());
+        }
+
         println!(
             "Installed components in {}:",
             self.korrect_bin_path.display()
         );
+        println!();
+        let rows: Vec<Vec<String>> = inventory
+            .versions
+            .iter()
+            .map(|kubectl| {
+                vec![
+                    kubectl.version.clone(),
+                    format_size(kubectl.size),
+                    kubectl
+                        .installed_at
+                        .map_or_else(|| "-".to_owned(), format_timestamp),
+                    kubectl
+                        .last_used
+                        .map_or_else(|| "never".to_owned(), format_timestamp),
+                    kubectl.checksum.to_string(),
+                    kubectl
+                        .contexts
+                        .iter()
+                        .cloned()
+                        .chain(
+                            kubectl
+                                .pinned
+                                .iter()
+                                .map(|context| format!("{} (pinned)", context)),
+                        )
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                ]
+            })
+            .collect();
+        if rows.is_empty() {
+            println!("No kubectl versions installed yet.");
+        } else {
+            print!(
+                "{}",
+                table(
+                    &[
+                        "version",
+                        "size",
+                        "installed",
+                        "last used",
+                        "checksum",
+                        "contexts"
+                    ],
+                    &rows
+                )
+            );
+        }
+
+        if !inventory.pins.is_empty() {
+            println!();
+            println!("Pinned contexts:");
+            let rows: Vec<Vec<String>> = inventory
+                .pins
+                .iter()
+                .map(|(context, pin)| {
+                    let installed = self.store().is_installed(&pin.version);
+                    vec![
+                        context.clone(),
+                        pin.version.clone(),
+                        pin.spec.clone(),
+                        if installed { "yes" } else { "no" }.to_owned(),
+                        format_timestamp(pin.pinned_at),
+                    ]
+                })
+                .collect();
+            print!(
+                "{}",
+                table(
+                    &["context", "version", "spec", "installed", "pinned"],
+                    &rows
+                )
+            );
+        }
+
+        println!();
+        println!("Shims and aliases:");
+        let rows: Vec<Vec<String>> = inventory
+            .shims
+            .iter()
+            .map(|shim| {
+                vec![
+                    shim.name.clone(),
+                    shim.kind.to_owned(),
+                    shim.target
+                        .as_ref()
+                        .map_or_else(String::new, |target| target.display().to_string()),
+                ]
+            })
+            .collect();
+        print!("{}", table(&["name", "kind", "target"], &rows));
+
+        Ok(())
+    }
+
+    /// Collects what is installed in the bin directory, mapping each kubectl
+    /// to the contexts the cache resolves or pins to it.
+    fn inventory(&self) -> anyhow::Result<Inventory> {
+        let store = self.store();
+        let entries = read_cache_entries(&self.korrect_cache_path);
+        let pins = read_pins(&self.korrect_config_path)?;
+
+        let mut versions = Vec::new();
+        for version in store.installed_versions() {
+            let path = store.kubectl_path(&version);
+            let metadata = fs::metadata(&path)?;
+            let installed_at = metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_secs());
+            let mut contexts: Vec<String> = entries
+                .iter()
+                .filter(|(_, entry)| entry.kubectl_version() == Some(version.as_str()))
+                .filter(|(_, entry)| !pins.contains_key(&entry.context))
+                .map(|(_, entry)| {
+                    if entry.context.is_empty() {
+                        entry.server.clone()
+                    } else {
+                        entry.context.clone()
+                    }
+                })
+                .collect();
+            contexts.dedup();
+            let pinned = pins
+                .iter()
+                .filter(|(_, pin)| pin.version == version)
+                .map(|(context, _)| context.clone())
+                .collect();
+            versions.push(InstalledKubectl {
+                size: metadata.len(),
+                installed_at,
+                last_used: store.last_used(&version),
+                checksum: store.verify(&version)?,
+                contexts,
+                pinned,
+                path,
+                version,
+            });
+        }
+
+        let installed = store.installed_versions();
+        let mut shims = Vec::new();
         for entry in fs::read_dir(&self.korrect_bin_path)? {
             let entry = entry?;
-            println!("- {}", entry.file_name().to_string_lossy());
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_kubectl = name
+                .strip_prefix("kubectl-")
+                .is_some_and(|version| installed.iter().any(|v| v == version));
+            if name.starts_with('.') || is_kubectl {
+                continue;
+            }
+            let (kind, target) = if entry.file_type()?.is_symlink() {
+                ("alias", fs::read_link(entry.path()).ok())
+            } else if name == "kubectl-shim" {
+                ("shim", None)
+            } else {
+                ("file", None)
+            };
+            shims.push(ShimEntry {
+                name,
+                kind,
+                path: entry.path(),
+                target,
+            });
+        }
+        shims.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Inventory {
+            versions,
+            shims,
+            pins,
+        })
+    }
+
+    fn which(
+        &self,
+        context: Option<String>,
+        args: &[String],
+        output: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let mut flags = ConnectionFlags::parse(args);
+        if context.is_some() {
+            flags.context = context;
+        }
+        let resolution = self.shim_config()?.resolve(&flags)?;
+        if print_structured(output, &resolution)? {
+            return Ok(());
+        }
+        print!("{}", describe_resolution(&resolution));
+        Ok(())
+    }
+
+    /// The shim's resolver, sharing this command's directories.
+    fn shim_config(&self) -> anyhow::Result<KorrectShimConfig> {
+        let mut config = KorrectShimConfig::new(false)?;
+        config.korrect_config_path = self.korrect_config_path.clone();
+        config.korrect_cache_path = self.korrect_cache_path.clone();
+        config.korrect_bin_path = self.korrect_bin_path.clone();
+        config.dl_url = self.dl_url.clone();
+        Ok(config)
+    }
+
+    fn cache(&self, command: CacheCommands) -> anyhow::Result<()> {
+        match command {
+            CacheCommands::List { output } => {
+                let listings: Vec<CacheListing> = read_cache_entries(&self.korrect_cache_path)
+                    .into_iter()
+                    .map(|(file, entry)| CacheListing { file, entry })
+                    .collect();
+                if !print_structured(output, &listings)? {
+                    print_cache_listings(&listings);
+                }
+            }
+            CacheCommands::Show { context, output } => {
+                let config = self.shim_config()?;
+                let target = config.resolve_cluster_target(&context_flags(context));
+                let file = config.get_version_cache_file(&target);
+                let Some(entry) = read_cache_entry(&file) else {
+                    return Err(anyhow!(
+                        "no cache entry for context {} on {}",
+                        target.context,
+                        target.server
+                    ));
+                };
+                let listing = CacheListing { file, entry };
+                if !print_structured(output, &listing)? {
+                    print_cache_listings(std::slice::from_ref(&listing));
+                }
+            }
+            CacheCommands::Refresh { contexts, all } => {
+                let flags: Vec<ConnectionFlags> = if all {
+                    read_cache_entries(&self.korrect_cache_path)
+                        .into_iter()
+                        .filter(|(_, entry)| !entry.context.is_empty())
+                        .map(|(_, entry)| ConnectionFlags {
+                            context: Some(entry.context),
+                            kubeconfig: entry
+                                .kubeconfig
+                                .map(|path| path.to_string_lossy().into_owned()),
+                            ..ConnectionFlags::default()
+                        })
+                        .collect()
+                } else if contexts.is_empty() {
+                    vec![ConnectionFlags::default()]
+                } else {
+                    contexts
+                        .into_iter()
+                        .map(|c| context_flags(Some(c)))
+                        .collect()
+                };
+                self.cache_refresh(&flags)?;
+            }
+            CacheCommands::Clear {
+                contexts,
+                failed,
+                all,
+            } => {
+                for file in self.cache_clear(&contexts, failed, all)? {
+                    println!("Removed {}", file.display());
+                }
+            }
+            CacheCommands::Prune { dry_run } => {
+                let config = self.shim_config()?;
+                for (file, entry) in read_cache_entries(&self.korrect_cache_path) {
+                    let Some(reason) = config.stale_reason(&entry) else {
+                        continue;
+                    };
+                    if !dry_run {
+                        fs::remove_file(&file)?;
+                    }
+                    println!(
+                        "{} {} on {}: {}",
+                        if dry_run { "Would prune" } else { "Pruned" },
+                        entry.context,
+                        entry.server,
+                        reason
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn cache_refresh(&self, flags: &[ConnectionFlags]) -> anyhow::Result<()> {
+        let config = self.shim_config()?;
+        let mut failed = 0;
+        let mut refreshed = HashSet::new();
+        for flags in flags {
+            // Contexts sharing a cluster share its entry
+            let target = config.resolve_cluster_target(flags);
+            if !refreshed.insert(config.get_version_cache_file(&target)) {
+                continue;
+            }
+            match config.refresh(flags) {
+                Ok(entry) => println!(
+                    "Refreshed {} on {}: {}",
+                    entry.context,
+                    entry.server,
+                    entry.server_version.or(entry.version).unwrap_or_default()
+                ),
+                Err(e) => {
+                    eprintln!(
+                        "Failed to refresh {} on {}: {:#}",
+                        target.context, target.server, e
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} refreshes failed",
+                failed,
+                refreshed.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Removes the selected cache entries, returning their files.
+    fn cache_clear(
+        &self,
+        contexts: &[String],
+        failed: bool,
+        all: bool,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let config = self.shim_config()?;
+        let mut files = HashSet::new();
+        if !all && (!contexts.is_empty() || !failed) {
+            let contexts: Vec<Option<String>> = if contexts.is_empty() {
+                vec![None]
+            } else {
+                contexts.iter().cloned().map(Some).collect()
+            };
+            for context in contexts {
+                let target = config.resolve_cluster_target(&context_flags(context));
+                files.insert(config.get_version_cache_file(&target));
+            }
+        }
+        for (file, entry) in read_cache_entries(&self.korrect_cache_path) {
+            if all || contexts.contains(&entry.context) || (failed && entry.version.is_none()) {
+                files.insert(file);
+            }
+        }
+
+        let mut removed: Vec<PathBuf> = files.into_iter().filter(|file| file.exists()).collect();
+        removed.sort();
+        for file in &removed {
+            fs::remove_file(file)?;
+        }
+        Ok(removed)
+    }
+
+    /// Resolves and runs kubectl through the shim's code for `korrect exec`,
+    /// replacing this process on Unix and otherwise returning kubectl's exit
+    /// code. With `print_path` only the binary is resolved.
+    fn exec(
+        &self,
+        version: Option<String>,
+        context: Option<String>,
+        kubeconfig: Option<String>,
+        print_path: bool,
+        args: &[String],
+    ) -> anyhow::Result<i32> {
+        let (config, args) = self.exec_config(version, context, kubeconfig, args)?;
+        if print_path {
+            let resolution = config.resolve_kubectl(&ConnectionFlags::parse(&args))?;
+            println!("{}", resolution.path.display());
+            return Ok(0);
+        }
+        config.exec_kubectl(&args)
+    }
+
+    /// The shim's resolver with `korrect exec`'s version applied, and the
+    /// kubectl arguments carrying its connection flags.
+    fn exec_config(
+        &self,
+        version: Option<String>,
+        context: Option<String>,
+        kubeconfig: Option<String>,
+        args: &[String],
+    ) -> anyhow::Result<(KorrectShimConfig, Vec<String>)> {
+        let mut config = self.shim_config()?;
+        config.flag_version = version;
+
+        // Passed on as kubectl's own flags, so resolution and kubectl agree on
+        // the cluster
+        let connection = ConnectionFlags {
+            context,
+            kubeconfig,
+            ..ConnectionFlags::default()
+        };
+        let args: Vec<String> = connection
+            .to_args()
+            .into_iter()
+            .chain(args.iter().cloned())
+            .collect();
+        Ok((config, args))
+    }
+
+    /// Runs `args` against every context matching `patterns` and prints a
+    /// summary, returning 0 only when kubectl succeeded everywhere.
+    fn fanout(
+        &self,
+        patterns: &[String],
+        kubeconfig: Option<String>,
+        parallelism: usize,
+        aggregate: bool,
+        args: &[String],
+    ) -> anyhow::Result<i32> {
+        let config = self.shim_config()?;
+        let flags = ConnectionFlags {
+            kubeconfig: kubeconfig.clone(),
+            ..ConnectionFlags::default()
+        };
+        let contexts = match_contexts(patterns, &config.context_names(&flags)?)?;
+        let fanout = Fanout {
+            config: &config,
+            kubeconfig,
+            parallelism,
+            aggregate,
+        };
+        let results = fanout.run(&contexts, args);
+
+        eprintln!();
+        eprint!("{}", fanout_summary(&results));
+        let failed = results.iter().filter(|result| !result.succeeded()).count();
+        if failed > 0 {
+            eprintln!("{} of {} contexts failed", failed, results.len());
+            return Ok(1);
+        }
+        Ok(0)
+    }
+
+    /// The kubeconfig's current context.
+    fn current_context(&self) -> anyhow::Result<String> {
+        let target = self
+            .shim_config()?
+            .resolve_cluster_target(&ConnectionFlags::default());
+        if target.context.is_empty() {
+            return Err(anyhow!("the kubeconfig has no current context"));
+        }
+        Ok(target.context)
+    }
+
+    /// Pins the version `spec` resolves to now to a context.
+    fn pin(&self, args: &[String], current: bool, allow_prerelease: bool) -> anyhow::Result<()> {
+        let (context, spec) = match (current, args) {
+            (true, [spec]) => (self.current_context()?, spec),
+            (false, [context, spec]) => (context.clone(), spec),
+            _ => {
+                return Err(anyhow!(
+                    "expected a context and a version, or only a version with --current"
+                ))
+            }
+        };
+        let version = self.store().resolve_spec(spec, allow_prerelease)?;
+
+        let mut pins = read_pins(&self.korrect_config_path)?;
+        pins.insert(
+            context.clone(),
+            Pin {
+                version: version.clone(),
+                spec: spec.clone(),
+                pinned_at: unix_now(),
+            },
+        );
+        write_pins(&self.korrect_config_path, &pins)?;
+        println!("Pinned {} to kubectl {}", context, version);
+        Ok(())
+    }
+
+    /// Removes the pins of `contexts`, the current context or all of them,
+    /// returning the unpinned contexts.
+    fn unpin(&self, contexts: &[String], current: bool, all: bool) -> anyhow::Result<Vec<String>> {
+        let mut pins = read_pins(&self.korrect_config_path)?;
+        let contexts: Vec<String> = if all {
+            pins.keys().cloned().collect()
+        } else if current {
+            vec![self.current_context()?]
+        } else {
+            contexts.to_vec()
+        };
+
+        let missing: Vec<&str> = contexts
+            .iter()
+            .filter(|context| pins.remove(*context).is_none())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow!("not pinned: {}", missing.join(", ")));
         }
+        write_pins(&self.korrect_config_path, &pins)?;
+        Ok(contexts)
+    }
+
+    fn store(&self) -> KubectlStore {
+        KubectlStore::new(self.korrect_bin_path.clone(), self.dl_url.clone())
+    }
 
+    fn install(&self, specs: &[String], allow_prerelease: bool) -> anyhow::Result<()> {
+        let store = self.store();
+        let mut failed = 0;
+        for spec in specs {
+            let installed = store
+                .resolve_spec(spec, allow_prerelease)
+                .and_then(|version| {
+                    let existed = store.is_installed(&version);
+                    store.install(&version).map(|path| (version, path, existed))
+                });
+            match installed {
+                Ok((version, path, true)) => {
+                    println!(
+                        "kubectl {} is already installed at {}",
+                        version,
+                        path.display()
+                    )
+                }
+                Ok((version, path, false)) => {
+                    println!("Installed kubectl {} to {}", version, path.display())
+                }
+                Err(e) => {
+                    eprintln!("Failed to install {}: {:#}", spec, e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!("{} of {} installs failed", failed, specs.len()));
+        }
         Ok(())
     }
+
+    fn uninstall(&self, versions: &[String]) -> anyhow::Result<()> {
+        let store = self.store();
+        let mut failed = 0;
+        for version in versions {
+            match store.uninstall(version) {
+                Ok(path) => println!("Removed {}", path.display()),
+                Err(e) => {
+                    eprintln!("Failed to uninstall {}: {:#}", version, e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} uninstalls failed",
+                failed,
+                versions.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct CacheListing {
+    file: PathBuf,
+    #[serde(flatten)]
+    entry: CacheEntry,
+}
+
+fn context_flags(context: Option<String>) -> ConnectionFlags {
+    ConnectionFlags {
+        context,
+        ..ConnectionFlags::default()
+    }
+}
+
+fn print_cache_listings(listings: &[CacheListing]) {
+    let now = unix_now();
+    let rows: Vec<Vec<String>> = listings
+        .iter()
+        .map(|CacheListing { entry, .. }| {
+            let checked_at = entry.failed_at.unwrap_or(0).max(entry.probed_at);
+            let status = match entry.failures {
+                0 => "ok".to_owned(),
+                n if entry.version.is_some() => format!("stale, {} failed probe(s)", n),
+                n => format!("failing, {} failed probe(s)", n),
+            };
+            vec![
+                entry.context.clone(),
+                entry.server.clone(),
+                entry.kubectl_version().unwrap_or("-").to_owned(),
+                entry
+                    .server_version
+                    .clone()
+                    .unwrap_or_else(|| "-".to_owned()),
+                format_age(now.saturating_sub(checked_at)),
+                status,
+            ]
+        })
+        .collect();
+    print!(
+        "{}",
+        table(
+            &["context", "server", "kubectl", "reported", "age", "status"],
+            &rows
+        )
+    );
+}
+
+fn fanout_summary(results: &[FanoutResult]) -> String {
+    let rows: Vec<Vec<String>> = results
+        .iter()
+        .map(|result| {
+            vec![
+                result.context.clone(),
+                result.version.clone().unwrap_or_else(|| "-".to_owned()),
+                result
+                    .exit_code
+                    .map_or_else(|| "-".to_owned(), |code| code.to_string()),
+                format!("{:.1}s", result.duration.as_secs_f64()),
+                result.error.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    table(&["context", "kubectl", "exit", "time", "error"], &rows)
+}
+
+/// Lays out a resolution as `korrect which` prints it.
+fn describe_resolution(resolution: &Resolution) -> String {
+    let mut rows = vec![
+        ("Path", resolution.path.display().to_string()),
+        ("Version", resolution.version.clone()),
+        ("Source", resolution.source.to_string()),
+    ];
+    if !resolution.installed {
+        rows[0].1.push_str(" (not installed yet)");
+    }
+    if !resolution.context.is_empty() {
+        rows.push(("Context", resolution.context.clone()));
+    }
+    if !resolution.server.is_empty() {
+        rows.push(("Server", resolution.server.clone()));
+    }
+    if let Some(file) = &resolution.version_file {
+        rows.push((
+            "Version file",
+            format!("{} ({})", file.path.display(), file.spec),
+        ));
+    }
+    if let Some(pin) = &resolution.pin {
+        rows.push((
+            "Pinned",
+            format!("{} on {}", pin.spec, format_timestamp(pin.pinned_at)),
+        ));
+    }
+    if let Some(server_version) = &resolution.server_version {
+        rows.push(("Server version", server_version.clone()));
+    }
+    rows.push(("Cache file", resolution.cache_file.display().to_string()));
+    if let Some(entry) = &resolution.cache_entry {
+        if let Some(reported) = &entry.server_version {
+            let distro = reported
+                .parse::<KubeVersion>()
+                .ok()
+                .and_then(|v| v.distro());
+            rows.push((
+                "Reported",
+                match distro {
+                    Some(distro) => format!("{} ({})", reported, distro),
+                    None => reported.clone(),
+                },
+            ));
+        }
+        if entry.probed_at > 0 {
+            rows.push(("Probed", format_timestamp(entry.probed_at)));
+        }
+        if entry.failures > 0 {
+            rows.push(("Failed probes", entry.failures.to_string()));
+        }
+    }
+
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0) + 1;
+    rows.iter()
+        .map(|(key, value)| format!("{:<width$} {}\n", format!("{}:", key), value, width = width))
+        .collect()
 }
 
 fn create_korrect_directories(korrect_dirs: Vec<&PathBuf>, force: bool) {
@@ -154,9 +878,56 @@ fn main() -> anyhow::Result<()> {
         }) => {
             korrect.setup(auto_download, force, uninstall)?;
         }
-        Some(Commands::List) => {
-            // Handle list command
-            korrect.list()?;
+        Some(Commands::List { output }) => {
+            korrect.list(output)?;
+        }
+        Some(Commands::Which {
+            context,
+            output,
+            args,
+        }) => {
+            korrect.which(context, &args, output)?;
+        }
+        Some(Commands::Cache { command }) => {
+            korrect.cache(command)?;
+        }
+        Some(Commands::Install { specs, pre }) => {
+            korrect.install(&specs, pre)?;
+        }
+        Some(Commands::Uninstall { versions }) => {
+            korrect.uninstall(&versions)?;
+        }
+        Some(Commands::Exec {
+            version,
+            context,
+            kubeconfig,
+            print_path,
+            args,
+        }) => {
+            let code = korrect.exec(version, context, kubeconfig, print_path, &args)?;
+            std::process::exit(code);
+        }
+        Some(Commands::Fanout {
+            contexts,
+            kubeconfig,
+            parallel,
+            aggregate,
+            args,
+        }) => {
+            let code = korrect.fanout(&contexts, kubeconfig, parallel, aggregate, &args)?;
+            std::process::exit(code);
+        }
+        Some(Commands::Pin { args, current, pre }) => {
+            korrect.pin(&args, current, pre)?;
+        }
+        Some(Commands::Unpin {
+            contexts,
+            current,
+            all,
+        }) => {
+            for context in korrect.unpin(&contexts, current, all)? {
+                println!("Unpinned {}", context);
+            }
         }
         _ => {
             Cli::command().print_help()?;
@@ -172,19 +943,54 @@ mod korrect_tests {
     use super::*;
     use std::env;
     use std::fs;
-    use std::path::PathBuf;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, MutexGuard};
 
     use tempfile::TempDir;
 
-    // Helper function to create a temporary home directory
-    fn setup_temp_home() -> (TempDir, String) {
-        let temp_dir = TempDir::new().unwrap();
-        let temp_home = temp_dir.path().to_str().unwrap().to_owned();
+    /// Serialises tests that change the process environment, chiefly `HOME`.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn env_lock() -> MutexGuard<'static, ()> {
+        ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A temporary `HOME`, holding the environment lock until dropped.
+    struct TempHome {
+        dir: TempDir,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Deref for TempHome {
+        type Target = TempDir;
+
+        fn deref(&self) -> &TempDir {
+            &self.dir
+        }
+    }
+
+    impl AsRef<Path> for TempHome {
+        fn as_ref(&self) -> &Path {
+            self.dir.path()
+        }
+    }
+
+    fn setup_temp_home() -> (TempHome, String) {
+        let lock = env_lock();
+        let dir = TempDir::new().unwrap();
+        let temp_home = dir.path().to_str().unwrap().to_owned();
         env::set_var("HOME", &temp_home);
-        (temp_dir, temp_home)
+        for (name, _) in env::vars_os() {
+            let name = name.to_string_lossy();
+            if name.starts_with("KORRECT_") || name == "KUBECONFIG" {
+                env::remove_var(name.as_ref());
+            }
+        }
+        (TempHome { dir, _lock: lock }, temp_home)
     }
 
-    fn remove_temp_dir(dir: TempDir) {
+    fn remove_temp_dir(dir: impl AsRef<Path>) {
         fs::remove_dir_all(dir).ok();
     }
 
@@ -216,6 +1022,7 @@ mod korrect_tests {
 
     #[test]
     fn test_korrect_new_with_custom_base_url() {
+        let _env = env_lock();
         // Set a custom base URL
         env::set_var("KORRECT_BASE_URL", "https://test.example.com");
 
@@ -229,6 +1036,7 @@ mod korrect_tests {
 
     #[test]
     fn test_korrect_new_default_base_url() {
+        let _env = env_lock();
         // Ensure no custom URL is set
         env::remove_var("KORRECT_BASE_URL");
 
@@ -290,7 +1098,7 @@ mod korrect_tests {
             let _handle = std::io::Cursor::new(&mut output);
             // Redirect stdout to our buffer
             let result = std::panic::catch_unwind(|| {
-                korrect.list().unwrap();
+                korrect.list(OutputFormat::Table).unwrap();
             });
 
             // Check if the result is as expected
@@ -326,7 +1134,7 @@ mod korrect_tests {
             let _handle = std::io::Cursor::new(&mut output);
             // Redirect stdout to our buffer
             let result = std::panic::catch_unwind(|| {
-                korrect.list().unwrap();
+                korrect.list(OutputFormat::Table).unwrap();
             });
 
             // Check if the result is as expected
@@ -342,6 +1150,196 @@ mod korrect_tests {
         assert!(output_str.contains("k"));
     }
 
+    #[test]
+    fn test_korrect_uninstall() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let korrect = Korrect::new().unwrap();
+        fs::create_dir_all(&korrect.korrect_bin_path).unwrap();
+        let installed = korrect.korrect_bin_path.join("kubectl-v1.29.3");
+        fs::write(&installed, "").unwrap();
+
+        assert!(korrect
+            .uninstall(&["v1.29.3".to_owned(), "v1.30.0".to_owned()])
+            .is_err());
+        assert!(!installed.exists());
+
+        remove_temp_dir(temp_dir);
+    }
+
+    #[test]
+    fn test_korrect_inventory() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let korrect = Korrect::new().unwrap();
+        let bin = &korrect.korrect_bin_path;
+        fs::create_dir_all(bin).unwrap();
+        fs::create_dir_all(&korrect.korrect_cache_path).unwrap();
+        fs::write(bin.join("kubectl-v1.29.3"), "kubectl").unwrap();
+        fs::write(bin.join("kubectl-v1.30.1"), "kubectl").unwrap();
+        fs::write(bin.join("kubectl-shim"), "shim").unwrap();
+        std::os::unix::fs::symlink(bin.join("kubectl-shim"), bin.join("kubectl")).unwrap();
+        korrect.store().record_use("v1.29.3");
+        let entry = korrect::cache::CacheEntry {
+            context: "dev".to_owned(),
+            server: "https://dev.example.com".to_owned(),
+            version: Some("v1.29.3".to_owned()),
+            ..Default::default()
+        };
+        fs::write(
+            korrect.korrect_cache_path.join("dev.json"),
+            serde_json::to_string(&entry).unwrap(),
+        )
+        .unwrap();
+
+        let inventory = korrect.inventory().unwrap();
+        let versions: Vec<&str> = inventory
+            .versions
+            .iter()
+            .map(|v| v.version.as_str())
+            .collect();
+        assert_eq!(versions, vec!["v1.29.3", "v1.30.1"]);
+        assert_eq!(inventory.versions[0].size, 7);
+        assert_eq!(inventory.versions[0].contexts, vec!["dev"]);
+        assert!(inventory.versions[0].last_used.is_some());
+        assert_eq!(inventory.versions[1].last_used, None);
+        assert_eq!(inventory.versions[1].checksum, ChecksumStatus::Unknown);
+
+        let shims: Vec<(&str, &str)> = inventory
+            .shims
+            .iter()
+            .map(|shim| (shim.name.as_str(), shim.kind))
+            .collect();
+        assert_eq!(shims, vec![("kubectl", "alias"), ("kubectl-shim", "shim")]);
+        assert_eq!(inventory.shims[0].target, Some(bin.join("kubectl-shim")));
+
+        remove_temp_dir(temp_dir);
+    }
+
+    #[test]
+    fn test_korrect_cache_clear() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let korrect = Korrect::new().unwrap();
+        fs::create_dir_all(&korrect.korrect_cache_path).unwrap();
+        let write = |name: &str, context: &str, version: Option<&str>| {
+            let entry = CacheEntry {
+                context: context.to_owned(),
+                version: version.map(str::to_owned),
+                ..Default::default()
+            };
+            let file = korrect.korrect_cache_path.join(name);
+            fs::write(&file, serde_json::to_string(&entry).unwrap()).unwrap();
+            file
+        };
+        let dev = write("dev.json", "dev", Some("v1.29.3"));
+        let prod = write("prod.json", "prod", Some("v1.30.1"));
+        let broken = write("broken.json", "broken", None);
+
+        assert_eq!(
+            korrect
+                .cache_clear(&["dev".to_owned()], true, false)
+                .unwrap(),
+            vec![broken.clone(), dev.clone()]
+        );
+        assert!(prod.exists());
+        assert_eq!(korrect.cache_clear(&[], false, true).unwrap(), vec![prod]);
+
+        remove_temp_dir(temp_dir);
+    }
+
+    #[test]
+    fn test_korrect_pin_and_unpin() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let korrect = Korrect::new().unwrap();
+        fs::create_dir_all(&korrect.korrect_bin_path).unwrap();
+        fs::write(korrect.korrect_bin_path.join("kubectl-v1.27.3"), "kubectl").unwrap();
+        korrect
+            .pin(&["prod".to_owned(), "v1.27.3".to_owned()], false, false)
+            .unwrap();
+        korrect
+            .pin(&["staging".to_owned(), "v1.28.0".to_owned()], false, false)
+            .unwrap();
+        assert!(korrect.pin(&["v1.28.0".to_owned()], false, false).is_err());
+
+        let inventory = korrect.inventory().unwrap();
+        assert_eq!(inventory.versions[0].pinned, vec!["prod"]);
+        assert_eq!(inventory.pins["staging"].version, "v1.28.0");
+        assert_eq!(inventory.pins["staging"].spec, "v1.28.0");
+
+        assert!(korrect.unpin(&["dev".to_owned()], false, false).is_err());
+        assert_eq!(
+            korrect.unpin(&["prod".to_owned()], false, false).unwrap(),
+            vec!["prod"]
+        );
+        assert_eq!(korrect.unpin(&[], false, true).unwrap(), vec!["staging"]);
+        assert!(read_pins(&korrect.korrect_config_path).unwrap().is_empty());
+
+        remove_temp_dir(temp_dir);
+    }
+
+    #[test]
+    fn test_korrect_exec() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let korrect = Korrect::new().unwrap();
+        fs::create_dir_all(&korrect.korrect_bin_path).unwrap();
+        let kubectl = korrect.korrect_bin_path.join("kubectl-v1.27.3");
+        let args_file = temp_dir.path().join("args");
+        fs::write(
+            &kubectl,
+            format!("#!/bin/sh\necho \"$@\" > {}\nexit 3\n", args_file.display()),
+        )
+        .unwrap();
+        korrect::download::make_executable(&kubectl).unwrap();
+
+        // Run as a child, as exec would replace the test process
+        let (config, args) = korrect
+            .exec_config(
+                Some("1.27".to_owned()),
+                Some("prod".to_owned()),
+                None,
+                &["get".to_owned(), "pods".to_owned()],
+            )
+            .unwrap();
+        assert_eq!(config.run_kubectl(&args).unwrap(), 3);
+        assert_eq!(
+            fs::read_to_string(&args_file).unwrap().trim(),
+            "--context=prod get pods"
+        );
+
+        remove_temp_dir(temp_dir);
+    }
+
+    #[test]
+    fn test_describe_resolution() {
+        let resolution = Resolution {
+            version: "v1.29.3".to_owned(),
+            path: PathBuf::from("/home/me/.korrect/bin/kubectl-v1.29.3"),
+            installed: false,
+            source: korrect::shim::VersionSource::Cache,
+            server_version: Some("v1.29.3".to_owned()),
+            context: "prod".to_owned(),
+            server: "https://prod.example.com".to_owned(),
+            cache_file: PathBuf::from("/home/me/.cache/korrect/abc.json"),
+            cache_entry: Some(korrect::cache::CacheEntry {
+                server_version: Some("v1.29.3-eks-adc7111".to_owned()),
+                ..Default::default()
+            }),
+            pin: None,
+            version_file: None,
+        };
+
+        let description = describe_resolution(&resolution);
+        assert!(description.contains(
+            "Path:           /home/me/.korrect/bin/kubectl-v1.29.3 (not installed yet)\n"
+        ));
+        assert!(description.contains("Source:         cache entry\n"));
+        assert!(description.contains("Reported:       v1.29.3-eks-adc7111 (EKS)\n"));
+        assert!(!description.contains("Probed:"));
+    }
+
     #[test]
     fn test_create_korrect_directories() {
         let temp_dir = TempDir::new().unwrap();
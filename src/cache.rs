@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Backoff before re-probing a cluster after a failed probe, doubled with
+/// every consecutive failure up to `MAX_PROBE_BACKOFF`
+const PROBE_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_PROBE_BACKOFF: Duration = Duration::from_secs(600);
+
+/// What the shim remembers about a cluster, stored as `<cache key>.json` in
+/// the cache directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub context: String,
+    pub server: String,
+    /// The kubeconfig named by `--kubeconfig` when the entry was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubeconfig: Option<PathBuf>,
+    /// The kubectl release for the version of the last successful probe
+    pub version: Option<String>,
+    /// The version exactly as the server reported it, e.g. `v1.29.3-eks-adc7111`
+    #[serde(default)]
+    pub server_version: Option<String>,
+    /// The kubectl release last run against the cluster when the selection
+    /// policy picked one other than `version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubectl: Option<String>,
+    /// Seconds since the Unix epoch
+    pub probed_at: u64,
+    /// Consecutive failed probes
+    #[serde(default)]
+    pub failures: u32,
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    pub failed_at: Option<u64>,
+}
+
+impl CacheEntry {
+    /// Whether a failed probe happened too recently to try again at `now`.
+    pub fn in_backoff(&self, now: u64) -> bool {
+        let Some(failed_at) = self.failed_at else {
+            return false;
+        };
+        let backoff = PROBE_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.failures.saturating_sub(1)))
+            .min(MAX_PROBE_BACKOFF);
+        now < failed_at.saturating_add(backoff.as_secs())
+    }
+
+    /// The kubectl release the shim runs for the cluster, if known.
+    pub fn kubectl_version(&self) -> Option<&str> {
+        self.kubectl.as_deref().or(self.version.as_deref())
+    }
+}
+
+pub fn read_cache_entry(cache_file: &Path) -> Option<CacheEntry> {
+    let contents = fs::read_to_string(cache_file).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Reads every cache entry in `cache_dir`, ordered by context and server.
+/// Files that are not cache entries are skipped.
+pub fn read_cache_entries(cache_dir: &Path) -> Vec<(PathBuf, CacheEntry)> {
+    let mut entries: Vec<(PathBuf, CacheEntry)> = fs::read_dir(cache_dir)
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .filter_map(|path| read_cache_entry(&path).map(|entry| (path, entry)))
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort_by(|(_, a), (_, b)| (&a.context, &a.server).cmp(&(&b.context, &b.server)));
+    entries
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_entry_backoff() {
+        let entry = CacheEntry {
+            failures: 1,
+            failed_at: Some(1_000),
+            ..CacheEntry::default()
+        };
+        assert!(entry.in_backoff(1_029));
+        assert!(!entry.in_backoff(1_030));
+
+        let entry = CacheEntry {
+            failures: 3,
+            ..entry
+        };
+        assert!(entry.in_backoff(1_119));
+        assert!(!entry.in_backoff(1_120));
+
+        let entry = CacheEntry {
+            failures: 30,
+            ..entry
+        };
+        assert!(!entry.in_backoff(1_600));
+        assert!(!CacheEntry::default().in_backoff(0));
+    }
+
+    #[test]
+    fn test_read_cache_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let entry = |context: &str, version: &str| CacheEntry {
+            context: context.to_owned(),
+            server: format!("https://{}.example.com", context),
+            version: Some(version.to_owned()),
+            ..CacheEntry::default()
+        };
+        for (name, entry) in [
+            ("b.json", entry("prod", "v1.29.3")),
+            ("a.json", entry("dev", "v1.30.1")),
+        ] {
+            fs::write(
+                temp_dir.path().join(name),
+                serde_json::to_string(&entry).unwrap(),
+            )
+            .unwrap();
+        }
+        fs::write(temp_dir.path().join("c.json"), "not json").unwrap();
+        fs::write(temp_dir.path().join("stale"), "v1.28.0").unwrap();
+
+        let entries = read_cache_entries(temp_dir.path());
+        let contexts: Vec<&str> = entries.iter().map(|(_, e)| e.context.as_str()).collect();
+        assert_eq!(contexts, vec!["dev", "prod"]);
+        assert_eq!(entries[1].0, temp_dir.path().join("b.json"));
+
+        let selected = CacheEntry {
+            kubectl: Some("v1.30.6".to_owned()),
+            ..entries[1].1.clone()
+        };
+        assert_eq!(entries[1].1.kubectl_version(), Some("v1.29.3"));
+        assert_eq!(selected.kubectl_version(), Some("v1.30.6"));
+    }
+}
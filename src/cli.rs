@@ -26,6 +26,14 @@ impl std::fmt::Display for ShellType {
     }
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
 #[derive(Parser)]
 #[command(
     author,
@@ -39,7 +47,7 @@ pub struct Cli {
     pub command: Option<Commands>,
 }
 
-#[derive(Subcommand, Clone, Copy)]
+#[derive(Subcommand, Clone)]
 pub enum Commands {
     #[clap(about = "Generates shell completions")]
     #[command(arg_required_else_help = true)]
@@ -66,8 +74,171 @@ pub enum Commands {
         #[clap(help = "Remove all korrect installed files")]
         uninstall: bool,
     },
-    #[clap(about = "Lists the installed components")]
-    List,
+    #[clap(about = "Lists the installed kubectl versions, shims and aliases")]
+    List {
+        #[arg(long, short, value_enum, default_value = "table")]
+        #[arg(help = "Output format")]
+        output: OutputFormat,
+    },
+    #[clap(about = "Shows which kubectl the shim would run and why, without running it")]
+    #[command(visible_alias = "explain")]
+    Which {
+        #[arg(
+            long,
+            help = "Resolve for this kubeconfig context instead of the current one"
+        )]
+        context: Option<String>,
+        #[arg(long, short, value_enum, default_value = "table")]
+        #[arg(help = "Output format")]
+        output: OutputFormat,
+        #[arg(
+            last = true,
+            help = "kubectl arguments, whose connection flags are honored"
+        )]
+        args: Vec<String>,
+    },
+    #[clap(about = "Inspects and manages the cached server versions")]
+    #[command(arg_required_else_help = true)]
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    #[clap(about = "Downloads kubectl versions into the korrect store")]
+    #[command(arg_required_else_help = true)]
+    Install {
+        #[arg(
+            required = true,
+            help = "Versions to install: v1.29.3, 1.29 (latest patch), stable, latest, stable-1.28 or latest-1.31"
+        )]
+        specs: Vec<String>,
+        #[clap(long, default_value = "false")]
+        #[clap(help = "Allow channels such as latest to resolve to pre-release versions")]
+        pre: bool,
+    },
+    #[clap(about = "Removes kubectl versions from the korrect store")]
+    #[command(arg_required_else_help = true)]
+    Uninstall {
+        #[arg(required = true, help = "Installed versions to remove, e.g. v1.29.3")]
+        versions: Vec<String>,
+    },
+    #[clap(
+        about = "Runs kubectl the way the shim does, optionally for a given version or cluster"
+    )]
+    #[command(visible_alias = "run")]
+    Exec {
+        #[arg(
+            long,
+            help = "Run this version instead of resolving one: v1.27.3, 1.27 (latest patch) or a channel"
+        )]
+        version: Option<String>,
+        #[arg(long, help = "Run against this kubeconfig context")]
+        context: Option<String>,
+        #[arg(long, help = "Run with this kubeconfig")]
+        kubeconfig: Option<String>,
+        #[clap(long, default_value = "false")]
+        #[clap(help = "Only print the path of the resolved kubectl, installing it if needed")]
+        print_path: bool,
+        #[arg(last = true, help = "Arguments passed on to kubectl")]
+        args: Vec<String>,
+    },
+    #[clap(about = "Runs a kubectl command against many contexts, each with its own kubectl")]
+    #[command(arg_required_else_help = true)]
+    Fanout {
+        #[arg(
+            long,
+            required = true,
+            value_delimiter = ',',
+            help = "Contexts to run against, as names or globs such as 'prod-*', separated by commas"
+        )]
+        contexts: Vec<String>,
+        #[arg(long, help = "Kubeconfig holding the contexts")]
+        kubeconfig: Option<String>,
+        #[arg(long, short = 'j', default_value = "8")]
+        #[arg(help = "How many contexts to run at once")]
+        parallel: usize,
+        #[clap(long, default_value = "false")]
+        #[clap(
+            help = "Print each context's output in one block when it finishes instead of prefixing every line"
+        )]
+        aggregate: bool,
+        #[arg(last = true, required = true, help = "Arguments passed on to kubectl")]
+        args: Vec<String>,
+    },
+    #[clap(about = "Pins a kubectl version to a context, skipping the cache and probes")]
+    #[command(arg_required_else_help = true)]
+    Pin {
+        #[arg(
+            required = true,
+            num_args = 1..=2,
+            value_names = ["CONTEXT", "VERSION"],
+            help = "The context and the version to pin: v1.27.3, 1.27 (latest patch), stable or a channel such as stable-1.27. Only the version with --current"
+        )]
+        args: Vec<String>,
+        #[clap(long, default_value = "false")]
+        #[clap(help = "Pin the current context")]
+        current: bool,
+        #[clap(long, default_value = "false")]
+        #[clap(help = "Allow channels such as latest to resolve to pre-release versions")]
+        pre: bool,
+    },
+    #[clap(about = "Removes pins so the shim resolves versions from the cluster again")]
+    #[command(arg_required_else_help = true)]
+    Unpin {
+        #[arg(
+            required_unless_present_any = ["current", "all"],
+            help = "Contexts to unpin"
+        )]
+        contexts: Vec<String>,
+        #[clap(long, default_value = "false", conflicts_with = "contexts")]
+        #[clap(help = "Unpin the current context")]
+        current: bool,
+        #[clap(long, default_value = "false", conflicts_with_all = ["contexts", "current"])]
+        #[clap(help = "Remove every pin")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum CacheCommands {
+    #[clap(about = "Lists the cached clusters with their version and age")]
+    List {
+        #[arg(long, short, value_enum, default_value = "table")]
+        #[arg(help = "Output format")]
+        output: OutputFormat,
+    },
+    #[clap(about = "Shows the cache entry for a context")]
+    Show {
+        #[arg(help = "Context to show, the current context by default")]
+        context: Option<String>,
+        #[arg(long, short, value_enum, default_value = "table")]
+        #[arg(help = "Output format")]
+        output: OutputFormat,
+    },
+    #[clap(about = "Re-probes clusters and updates their cache entries")]
+    Refresh {
+        #[arg(help = "Contexts to refresh, the current context by default")]
+        contexts: Vec<String>,
+        #[clap(long, default_value = "false", conflicts_with = "contexts")]
+        #[clap(help = "Refresh every cached cluster")]
+        all: bool,
+    },
+    #[clap(about = "Removes cache entries so the next command re-probes")]
+    Clear {
+        #[arg(help = "Contexts to clear, the current context by default")]
+        contexts: Vec<String>,
+        #[clap(long, default_value = "false")]
+        #[clap(help = "Also clear every entry whose last probe failed")]
+        failed: bool,
+        #[clap(long, default_value = "false", conflicts_with_all = ["contexts", "failed"])]
+        #[clap(help = "Clear the whole cache")]
+        all: bool,
+    },
+    #[clap(about = "Removes entries whose kubeconfig or context no longer exists")]
+    Prune {
+        #[clap(long, default_value = "false")]
+        #[clap(help = "Only show what would be removed")]
+        dry_run: bool,
+    },
 }
 
 pub fn generate_completions(shell: Option<ShellType>, help: bool) -> Result<(), Error> {
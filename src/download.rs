@@ -0,0 +1,542 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+
+/// Network settings used for every request to the kubectl download server.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn from_env() -> Self {
+        let mut options = Self::default();
+        if let Some(secs) = crate::env_parse::<u64>("KORRECT_CONNECT_TIMEOUT") {
+            options.connect_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = crate::env_parse::<u64>("KORRECT_READ_TIMEOUT") {
+            options.read_timeout = Duration::from_secs(secs);
+        }
+        if let Some(retries) = crate::env_parse::<u32>("KORRECT_DOWNLOAD_RETRIES") {
+            options.max_retries = retries;
+        }
+        options
+    }
+
+    pub fn client(&self) -> Result<Client> {
+        // The blocking client applies `timeout` to every individual read of the
+        // body, so it acts as a read timeout rather than capping the transfer.
+        Ok(Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.read_timeout)
+            .build()?)
+    }
+
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    HttpStatus { url: String, status: StatusCode },
+    NotExecutable { url: String, reason: String },
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::HttpStatus { url, status } => {
+                write!(f, "GET {} returned HTTP {}", url, status)
+            }
+            DownloadError::NotExecutable { url, reason } => {
+                write!(f, "{} is not a usable kubectl binary: {}", url, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+pub fn detect_os() -> String {
+    match env::consts::OS {
+        "macos" => "darwin".to_string(),
+        "windows" => "windows".to_string(),
+        _ => "linux".to_string(),
+    }
+}
+
+pub fn detect_cpu_arch() -> String {
+    match env::consts::ARCH {
+        "x86" => "386".to_string(),
+        "x86_64" => "amd64".to_string(),
+        "arm" => "arm".to_string(),
+        "aarch64" => "arm64".to_string(),
+        _ => env::consts::ARCH.to_string(),
+    }
+}
+
+pub fn download_file_with_progress(
+    url: &str,
+    dest: &mut File,
+    options: &DownloadOptions,
+) -> Result<()> {
+    let client = options.client()?;
+
+    // Create a progress bar
+    let pb = ProgressBar::new(0);
+    // pb.set_style(ProgressStyle::default_spinner());
+    pb.set_style(ProgressStyle::default_bar().template("{msg} {spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
+    .progress_chars("#>-"));
+    pb.set_message(format!("Downloading {}", &url));
+
+    let mut attempt = 0;
+    loop {
+        match download_attempt(&client, url, dest, &pb) {
+            Ok(()) => break,
+            Err(err) if err.transient && attempt < options.max_retries => {
+                let delay = options.backoff(attempt);
+                attempt += 1;
+                pb.println(format!(
+                    "Download interrupted ({}), retrying in {:?} [{}/{}]",
+                    err.error, delay, attempt, options.max_retries
+                ));
+                std::thread::sleep(delay);
+            }
+            Err(err) => return Err(err.error),
+        }
+    }
+
+    // Complete the progress bar
+    // pb.finish_with_message("Download complete");
+
+    // Make sure the bytes are on disk before the caller renames the file into place
+    dest.sync_all()?;
+
+    Ok(())
+}
+
+struct AttemptError {
+    error: anyhow::Error,
+    transient: bool,
+}
+
+impl AttemptError {
+    fn transient(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            transient: true,
+        }
+    }
+
+    fn fatal(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            transient: false,
+        }
+    }
+}
+
+/// Fetches whatever is still missing from `dest`, resuming from its current
+/// length with a `Range` request when earlier attempts left partial data.
+fn download_attempt(
+    client: &Client,
+    url: &str,
+    dest: &mut File,
+    pb: &ProgressBar,
+) -> Result<(), AttemptError> {
+    let mut downloaded = dest.metadata().map_err(AttemptError::fatal)?.len();
+
+    let mut request = client.get(url);
+    if downloaded > 0 {
+        request = request.header(RANGE, format!("bytes={}-", downloaded));
+    }
+    let mut response = request.send().map_err(AttemptError::transient)?;
+
+    let status = response.status();
+    let expected_total = match status {
+        StatusCode::PARTIAL_CONTENT => content_range_total(&response)
+            .or_else(|| response.content_length().map(|len| downloaded + len)),
+        StatusCode::RANGE_NOT_SATISFIABLE if downloaded > 0 => {
+            // Either the file is already complete or the partial data is stale;
+            // only the former is known once the server reports the full size.
+            if content_range_total(&response) == Some(downloaded) {
+                return Ok(());
+            }
+            truncate(dest).map_err(AttemptError::fatal)?;
+            return Err(AttemptError::transient(anyhow!(
+                "server rejected resume at byte {}",
+                downloaded
+            )));
+        }
+        _ if status.is_success() => {
+            // The server ignored the range, so start over
+            if downloaded > 0 {
+                truncate(dest).map_err(AttemptError::fatal)?;
+                downloaded = 0;
+            }
+            response.content_length()
+        }
+        _ => {
+            let transient = status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS;
+            let error = anyhow!(DownloadError::HttpStatus {
+                url: url.to_owned(),
+                status,
+            });
+            return Err(AttemptError { error, transient });
+        }
+    };
+
+    pb.set_length(expected_total.unwrap_or(0));
+    pb.set_position(downloaded);
+
+    // Buffer for reading chunks
+    let mut buffer = vec![0; 8192]; // 8KB chunks
+
+    // Download with progress tracking
+    loop {
+        let bytes_read = response
+            .read(&mut buffer)
+            .map_err(AttemptError::transient)?;
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        dest.write_all(&buffer[0..bytes_read])
+            .map_err(AttemptError::fatal)?;
+        downloaded += bytes_read as u64;
+        pb.set_position(downloaded);
+    }
+
+    match expected_total {
+        Some(total) if downloaded < total => Err(AttemptError::transient(anyhow!(
+            "connection closed after {} of {} bytes",
+            downloaded,
+            total
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the full resource size from a `Content-Range: bytes a-b/total` header.
+fn content_range_total(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn truncate(file: &mut File) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+pub fn check_status(response: Response, url: &str) -> Result<Response, DownloadError> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(DownloadError::HttpStatus {
+            url: url.to_owned(),
+            status,
+        })
+    }
+}
+
+/// Checks the file header against the executable format expected for the
+/// given kubectl `os`/`cpu_arch` pair, returning the reason when it does not match.
+pub fn validate_executable(path: &Path, os: &str, cpu_arch: &str) -> Result<(), String> {
+    let mut header = Vec::with_capacity(64);
+    File::open(path)
+        .and_then(|file| file.take(64).read_to_end(&mut header))
+        .map_err(|e| e.to_string())?;
+
+    match os {
+        "linux" => {
+            if header.len() < 20 || &header[0..4] != b"\x7fELF" {
+                return Err("payload is not an ELF executable".to_owned());
+            }
+            let machine = match header[5] {
+                1 => u16::from_le_bytes([header[18], header[19]]),
+                2 => u16::from_be_bytes([header[18], header[19]]),
+                _ => return Err("ELF header has an unknown byte order".to_owned()),
+            };
+            let expected = match cpu_arch {
+                "386" => 3,
+                "amd64" => 62,
+                "arm" => 40,
+                "arm64" => 183,
+                "ppc64le" => 21,
+                "s390x" => 22,
+                _ => return Ok(()),
+            };
+            if machine != expected {
+                return Err(format!(
+                    "ELF machine type {} does not match {}",
+                    machine, cpu_arch
+                ));
+            }
+            Ok(())
+        }
+        "darwin" => {
+            if header.len() < 8 {
+                return Err("payload is not a Mach-O executable".to_owned());
+            }
+            // Universal binaries carry every architecture
+            if header[0..4] == [0xca, 0xfe, 0xba, 0xbe] {
+                return Ok(());
+            }
+            if header[0..4] != [0xcf, 0xfa, 0xed, 0xfe] {
+                return Err("payload is not a Mach-O executable".to_owned());
+            }
+            let cpu_type = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let expected = match cpu_arch {
+                "amd64" => 0x0100_0007,
+                "arm64" => 0x0100_000c,
+                _ => return Ok(()),
+            };
+            if cpu_type != expected {
+                return Err(format!(
+                    "Mach-O cpu type {:#x} does not match {}",
+                    cpu_type, cpu_arch
+                ));
+            }
+            Ok(())
+        }
+        "windows" => {
+            if !header.starts_with(b"MZ") {
+                return Err("payload is not a PE executable".to_owned());
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 8192];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[0..bytes_read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn make_executable(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(path)?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(path, perms)?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod download_tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn test_options() -> DownloadOptions {
+        DownloadOptions {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            ..DownloadOptions::default()
+        }
+    }
+    #[test]
+    fn test_detect_os() {
+        let os = detect_os();
+        match env::consts::OS {
+            "macos" => assert_eq!(os, "darwin"),
+            "windows" => assert_eq!(os, "windows"),
+            _ => assert_eq!(os, "linux"),
+        }
+    }
+
+    #[test]
+    fn test_detect_cpu_arch() {
+        let arch = detect_cpu_arch();
+        match env::consts::ARCH {
+            "x86" => assert_eq!(arch, "386"),
+            "x86_64" => assert_eq!(arch, "amd64"),
+            "arm" => assert_eq!(arch, "arm"),
+            "aarch64" => assert_eq!(arch, "arm64"),
+            _ => assert_eq!(arch, env::consts::ARCH),
+        }
+    }
+
+    #[test]
+    fn test_validate_executable() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("kubectl");
+
+        let mut elf = vec![0u8; 64];
+        elf[0..4].copy_from_slice(b"\x7fELF");
+        elf[5] = 1;
+        elf[18..20].copy_from_slice(&183u16.to_le_bytes());
+        fs::write(&path, &elf).unwrap();
+        assert!(validate_executable(&path, "linux", "arm64").is_ok());
+        assert!(validate_executable(&path, "linux", "amd64").is_err());
+        assert!(validate_executable(&path, "darwin", "arm64").is_err());
+
+        fs::write(&path, b"<html></html>").unwrap();
+        assert!(validate_executable(&path, "linux", "amd64").is_err());
+    }
+
+    #[test]
+    fn test_download_file_with_progress() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let test_file_content = b"v1.3.0";
+
+        server
+            .mock("GET", "/test-file")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_header("x-api-key", "1234")
+            .with_body(test_file_content)
+            .create();
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("test-file");
+
+        let url = format!("{url}/test-file");
+        let mut output_file = File::create(&output_path).unwrap();
+        let result = download_file_with_progress(&url, &mut output_file, &test_options());
+
+        assert!(result.is_ok());
+        assert!(output_path.exists());
+        assert_eq!(std::fs::read(&output_path).unwrap(), test_file_content);
+    }
+
+    #[test]
+    fn test_download_file_resumes_partial_download() {
+        let mut server = mockito::Server::new();
+        let content = b"0123456789abcdef";
+
+        let resume = server
+            .mock("GET", "/kubectl")
+            .match_header("range", "bytes=10-")
+            .with_status(206)
+            .with_header("content-range", "bytes 10-15/16")
+            .with_body(&content[10..])
+            .create();
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("kubectl");
+        fs::write(&output_path, &content[..10]).unwrap();
+
+        let mut output_file = fs::OpenOptions::new()
+            .append(true)
+            .open(&output_path)
+            .unwrap();
+        let url = format!("{}/kubectl", server.url());
+        download_file_with_progress(&url, &mut output_file, &test_options()).unwrap();
+
+        resume.assert();
+        assert_eq!(fs::read(&output_path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_download_file_retries_server_errors() {
+        let mut server = mockito::Server::new();
+        let content = b"eventually consistent";
+
+        let failure = server
+            .mock("GET", "/kubectl")
+            .with_status(503)
+            .expect(2)
+            .create();
+        let success = server
+            .mock("GET", "/kubectl")
+            .with_status(200)
+            .with_body(content)
+            .expect(1)
+            .create();
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("kubectl");
+        let mut output_file = File::create(&output_path).unwrap();
+        let url = format!("{}/kubectl", server.url());
+        download_file_with_progress(&url, &mut output_file, &test_options()).unwrap();
+
+        failure.assert();
+        success.assert();
+        assert_eq!(fs::read(&output_path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_download_file_gives_up_on_client_errors() {
+        let mut server = mockito::Server::new();
+        let not_found = server
+            .mock("GET", "/kubectl")
+            .with_status(404)
+            .expect(1)
+            .create();
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut output_file = File::create(temp_dir.path().join("kubectl")).unwrap();
+        let url = format!("{}/kubectl", server.url());
+        let result = download_file_with_progress(&url, &mut output_file, &test_options());
+
+        assert!(result.is_err());
+        not_found.assert();
+    }
+
+    #[test]
+    fn test_download_options_backoff() {
+        let options = DownloadOptions::default();
+        assert_eq!(options.backoff(0), Duration::from_millis(500));
+        assert_eq!(options.backoff(1), Duration::from_secs(1));
+        assert_eq!(options.backoff(3), Duration::from_secs(4));
+        assert_eq!(options.backoff(10), Duration::from_secs(8));
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::process::{exit_code, kubectl_command};
+use crate::shim::{ConnectionFlags, KorrectShimConfig, Resolution};
+
+/// How one context of a fan-out went.
+#[derive(Debug)]
+pub struct FanoutResult {
+    pub context: String,
+    /// The kubectl release run, unless resolution failed
+    pub version: Option<String>,
+    /// kubectl's exit code, unless it could not be run
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    /// How long kubectl ran
+    pub duration: Duration,
+}
+
+impl FanoutResult {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs one kubectl command against many contexts, each with the kubectl the
+/// shim would pick for it.
+pub struct Fanout<'a> {
+    pub config: &'a KorrectShimConfig,
+    /// The kubeconfig holding the contexts, else the default one
+    pub kubeconfig: Option<String>,
+    /// How many contexts are resolved or run at once
+    pub parallelism: usize,
+    /// Print each context's output in one block when it finishes, instead of
+    /// streaming lines prefixed with the context
+    pub aggregate: bool,
+}
+
+impl Fanout<'_> {
+    /// Resolves kubectl for every context in parallel, installs the missing
+    /// versions one at a time so no version is downloaded twice, then runs
+    /// `args` everywhere. Results are in the order of `contexts`.
+    pub fn run(&self, contexts: &[String], args: &[String]) -> Vec<FanoutResult> {
+        let mut resolutions: Vec<Result<Resolution>> =
+            parallel_map(contexts, self.parallelism, |context| {
+                self.config.resolve(&self.flags(context))
+            });
+        for resolution in resolutions.iter_mut() {
+            if let Ok(resolved) = resolution {
+                if let Err(e) = self.config.install_resolution(resolved) {
+                    *resolution = Err(e);
+                }
+            }
+        }
+
+        let width = contexts.iter().map(String::len).max().unwrap_or(0);
+        let jobs: Vec<(&String, Result<Resolution>)> = contexts.iter().zip(resolutions).collect();
+        parallel_map(&jobs, self.parallelism, |(context, resolution)| {
+            let started = Instant::now();
+            let mut result = FanoutResult {
+                context: context.to_string(),
+                version: None,
+                exit_code: None,
+                error: None,
+                duration: Duration::ZERO,
+            };
+            match resolution {
+                Ok(resolution) => {
+                    result.version = Some(resolution.version.clone());
+                    match self.run_one(context, &resolution.path, args, width) {
+                        Ok(code) => result.exit_code = Some(code),
+                        Err(e) => result.error = Some(format!("{:#}", e)),
+                    }
+                    result.duration = started.elapsed();
+                }
+                Err(e) => result.error = Some(format!("{:#}", e)),
+            }
+            result
+        })
+    }
+
+    fn flags(&self, context: &str) -> ConnectionFlags {
+        ConnectionFlags {
+            context: Some(context.to_owned()),
+            kubeconfig: self.kubeconfig.clone(),
+            ..ConnectionFlags::default()
+        }
+    }
+
+    fn run_one(&self, context: &str, kubectl: &Path, args: &[String], width: usize) -> Result<i32> {
+        let mut child = kubectl_command(kubectl)
+            .args(self.flags(context).to_args())
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("no stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| anyhow!("no stderr"))?;
+
+        if self.aggregate {
+            let (out, err) = thread::scope(|scope| {
+                let err = scope.spawn(|| read_all(stderr));
+                (read_all(stdout), err.join().unwrap_or_default())
+            });
+            let status = child.wait()?;
+            let mut out_lock = io::stdout().lock();
+            writeln!(out_lock, "==> {} <==", context).ok();
+            out_lock.write_all(&out).ok();
+            out_lock.flush().ok();
+            io::stderr().write_all(&err).ok();
+            return Ok(exit_code(status));
+        }
+
+        let prefix = format!("{:<width$} | ", context, width = width);
+        thread::scope(|scope| {
+            scope.spawn(|| copy_prefixed(stderr, io::stderr(), &prefix));
+            copy_prefixed(stdout, io::stdout(), &prefix);
+        });
+        Ok(exit_code(child.wait()?))
+    }
+}
+
+fn read_all(mut reader: impl Read) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).ok();
+    buffer
+}
+
+/// Copies `reader` to `writer` line by line, each line written whole so
+/// contexts running at once do not interleave within a line.
+fn copy_prefixed(reader: impl Read, writer: impl Write, prefix: &str) {
+    let mut writer = writer;
+    for line in BufReader::new(reader).split(b'\n') {
+        let Ok(line) = line else {
+            break;
+        };
+        let mut buffer = Vec::with_capacity(prefix.len() + line.len() + 1);
+        buffer.extend_from_slice(prefix.as_bytes());
+        buffer.extend_from_slice(&line);
+        buffer.push(b'\n');
+        if writer.write_all(&buffer).is_err() {
+            break;
+        }
+    }
+}
+
+/// Maps `items` with up to `parallelism` threads, keeping their order.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    parallelism: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is mapped"))
+        .collect()
+}
+
+/// Expands `patterns`, context names or globs using `*` and `?`, against the
+/// kubeconfig's `contexts`, keeping kubeconfig order. A pattern that matches
+/// nothing is an error rather than silently running against fewer clusters.
+pub fn match_contexts(patterns: &[String], contexts: &[String]) -> Result<Vec<String>> {
+    let unmatched: Vec<&str> = patterns
+        .iter()
+        .filter(|pattern| !contexts.iter().any(|context| glob_match(pattern, context)))
+        .map(String::as_str)
+        .collect();
+    if !unmatched.is_empty() {
+        return Err(anyhow!("no context matches {}", unmatched.join(", ")));
+    }
+    Ok(contexts
+        .iter()
+        .filter(|context| patterns.iter().any(|pattern| glob_match(pattern, context)))
+        .cloned()
+        .collect())
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // Backtracks to the last `*`, which is enough for patterns without classes
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod fanout_tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::test_support::{setup_temp_home, test_config};
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("prod-*", "prod-eu-1"));
+        assert!(glob_match("*-eu-?", "prod-eu-1"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("staging", "staging"));
+        assert!(!glob_match("prod-*", "staging"));
+        assert!(!glob_match("prod-?", "prod-10"));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+    }
+
+    #[test]
+    fn test_match_contexts() {
+        let contexts = names(&["prod-us", "staging", "prod-eu", "dev"]);
+        assert_eq!(
+            match_contexts(&names(&["dev", "prod-*"]), &contexts).unwrap(),
+            names(&["prod-us", "prod-eu", "dev"])
+        );
+        let err = match_contexts(&names(&["prod-*", "qa"]), &contexts).unwrap_err();
+        assert_eq!(err.to_string(), "no context matches qa");
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let doubled = parallel_map(&items, 4, |item| {
+            thread::sleep(Duration::from_millis(20 - item));
+            item * 2
+        });
+        assert_eq!(doubled, (0..20).map(|item| item * 2).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_fanout_run() {
+        let (_home, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        config.auto_download = false;
+        config.flag_version = Some("v1.27.3".to_owned());
+
+        let kubectl = config.korrect_bin_path.join("kubectl-v1.27.3");
+        fs::write(
+            &kubectl,
+            "#!/bin/sh\ncase \"$1\" in --context=bad) exit 2 ;; esac\n",
+        )
+        .unwrap();
+        crate::download::make_executable(&kubectl).unwrap();
+
+        let fanout = Fanout {
+            config: &config,
+            kubeconfig: None,
+            parallelism: 2,
+            aggregate: false,
+        };
+        let results = fanout.run(&names(&["good", "bad", "other"]), &names(&["get", "nodes"]));
+        let codes: Vec<(&str, Option<i32>)> = results
+            .iter()
+            .map(|result| (result.context.as_str(), result.exit_code))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![("good", Some(0)), ("bad", Some(2)), ("other", Some(0))]
+        );
+        assert_eq!(results[0].version.as_deref(), Some("v1.27.3"));
+
+        // Failed resolutions are reported per context
+        config.flag_version = Some("v1.28.0".to_owned());
+        let fanout = Fanout {
+            config: &config,
+            kubeconfig: None,
+            parallelism: 2,
+            aggregate: false,
+        };
+        let results = fanout.run(&names(&["good"]), &names(&["get", "nodes"]));
+        assert_eq!(results[0].exit_code, None);
+        assert!(results[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("not installed"));
+    }
+}
@@ -0,0 +1,535 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// A kubeconfig file, or the merged view of several, as understood by kubectl.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Kubeconfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub clusters: Vec<NamedCluster>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub contexts: Vec<NamedContext>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub users: Vec<NamedUser>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub extensions: Vec<NamedExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedCluster {
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub cluster: Cluster,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cluster {
+    #[serde(default)]
+    pub server: Option<String>,
+    #[serde(default)]
+    pub certificate_authority: Option<String>,
+    #[serde(default)]
+    pub certificate_authority_data: Option<String>,
+    #[serde(default)]
+    pub insecure_skip_tls_verify: bool,
+    #[serde(default)]
+    pub tls_server_name: Option<String>,
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub extensions: Vec<NamedExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedContext {
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub context: Context,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    #[serde(default)]
+    pub cluster: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub extensions: Vec<NamedExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedUser {
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub user: User,
+}
+
+/// Credentials for a user. Exec plugins and auth providers are kept as raw
+/// YAML since korrect only needs to know whether they are present.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct User {
+    #[serde(default)]
+    pub client_certificate: Option<String>,
+    #[serde(default)]
+    pub client_certificate_data: Option<String>,
+    #[serde(default)]
+    pub client_key: Option<String>,
+    #[serde(default)]
+    pub client_key_data: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub token_file: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub exec: Option<serde_yaml::Value>,
+    #[serde(default)]
+    pub auth_provider: Option<serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedExtension {
+    pub name: String,
+    #[serde(default)]
+    pub extension: serde_yaml::Value,
+}
+
+/// A context together with the cluster and user it refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedContext<'a> {
+    pub name: &'a str,
+    pub context: &'a Context,
+    pub cluster: &'a Cluster,
+    pub user: Option<&'a User>,
+}
+
+#[derive(Debug)]
+pub enum KubeconfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: Option<PathBuf>,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    NotFound(Vec<PathBuf>),
+    NoCurrentContext,
+    ContextNotFound(String),
+    ClusterNotFound(String),
+}
+
+impl fmt::Display for KubeconfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KubeconfigError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            KubeconfigError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                match path {
+                    Some(path) => write!(f, "{}", path.display())?,
+                    None => write!(f, "<kubeconfig>")?,
+                }
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                    if let Some(column) = column {
+                        write!(f, ":{}", column)?;
+                    }
+                }
+                write!(f, ": {}", message)
+            }
+            KubeconfigError::NotFound(paths) => {
+                write!(f, "no kubeconfig found (looked in {:?})", paths)
+            }
+            KubeconfigError::NoCurrentContext => write!(f, "kubeconfig has no current-context"),
+            KubeconfigError::ContextNotFound(name) => {
+                write!(f, "context {} not found in kubeconfig", name)
+            }
+            KubeconfigError::ClusterNotFound(name) => {
+                write!(f, "cluster {} not found in kubeconfig", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KubeconfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KubeconfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Kubeconfig {
+    /// Parses a single kubeconfig document. `path` is only used to locate
+    /// parse errors.
+    pub fn parse(contents: &str, path: Option<&Path>) -> Result<Self, KubeconfigError> {
+        // An empty file is a valid, empty kubeconfig to kubectl
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(contents).map_err(|e| KubeconfigError::Parse {
+            path: path.map(Path::to_path_buf),
+            line: e.location().map(|location| location.line()),
+            column: e.location().map(|location| location.column()),
+            message: e.to_string(),
+        })
+    }
+
+    /// Reads a kubeconfig file, making the file paths it references absolute.
+    pub fn read(path: &Path) -> Result<Self, KubeconfigError> {
+        let contents = fs::read_to_string(path).map_err(|source| KubeconfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut config = Self::parse(&contents, Some(path))?;
+        if let Some(dir) = path.parent() {
+            config.resolve_relative_paths(dir);
+        }
+        Ok(config)
+    }
+
+    /// Loads and merges `paths` following kubectl's rules: the first file to
+    /// set `current-context` wins, as does the first definition of every named
+    /// cluster, context, user and extension. Files that do not exist are
+    /// skipped, but at least one must be present.
+    pub fn load(paths: &[PathBuf]) -> Result<Self, KubeconfigError> {
+        let mut merged: Option<Self> = None;
+        for path in paths {
+            let config = match Self::read(path) {
+                Ok(config) => config,
+                Err(KubeconfigError::Io { source, .. }) if source.kind() == ErrorKind::NotFound => {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            match merged.as_mut() {
+                Some(merged) => merged.merge(config),
+                None => merged = Some(config),
+            }
+        }
+        merged.ok_or_else(|| KubeconfigError::NotFound(paths.to_vec()))
+    }
+
+    /// Merges `other` into `self` without overriding anything already set.
+    pub fn merge(&mut self, other: Self) {
+        if self
+            .current_context
+            .as_deref()
+            .unwrap_or_default()
+            .is_empty()
+        {
+            self.current_context = other.current_context;
+        }
+        merge_named(&mut self.clusters, other.clusters, |c| &c.name);
+        merge_named(&mut self.contexts, other.contexts, |c| &c.name);
+        merge_named(&mut self.users, other.users, |u| &u.name);
+        merge_named(&mut self.extensions, other.extensions, |e| &e.name);
+    }
+
+    pub fn context(&self, name: &str) -> Option<&Context> {
+        self.contexts
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| &c.context)
+    }
+
+    pub fn cluster(&self, name: &str) -> Option<&Cluster> {
+        self.clusters
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| &c.cluster)
+    }
+
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.iter().find(|u| u.name == name).map(|u| &u.user)
+    }
+
+    /// The context kubectl would use by default, if any.
+    pub fn current_context(&self) -> Option<&str> {
+        self.current_context
+            .as_deref()
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Resolves `context`, or the current context, to its cluster and user.
+    /// The user is `None` when the context names none or one that is missing.
+    pub fn resolve<'a>(
+        &'a self,
+        context: Option<&'a str>,
+    ) -> Result<ResolvedContext<'a>, KubeconfigError> {
+        let name = match context {
+            Some(name) => name,
+            None => self
+                .current_context()
+                .ok_or(KubeconfigError::NoCurrentContext)?,
+        };
+        let resolved_context = self
+            .context(name)
+            .ok_or_else(|| KubeconfigError::ContextNotFound(name.to_owned()))?;
+        let cluster = self
+            .cluster(&resolved_context.cluster)
+            .ok_or_else(|| KubeconfigError::ClusterNotFound(resolved_context.cluster.clone()))?;
+        // A dangling user reference only matters for authentication, which
+        // kubectl reports on its own
+        let user = resolved_context
+            .user
+            .as_deref()
+            .and_then(|user| self.user(user));
+        Ok(ResolvedContext {
+            name,
+            context: resolved_context,
+            cluster,
+            user,
+        })
+    }
+
+    /// Like kubectl, treats relative file references as relative to the
+    /// kubeconfig file that contains them.
+    fn resolve_relative_paths(&mut self, dir: &Path) {
+        let absolute = |file: &mut Option<String>| {
+            if let Some(path) = file
+                .as_mut()
+                .filter(|p| Path::new(p.as_str()).is_relative())
+            {
+                *path = dir.join(&path).to_string_lossy().into_owned();
+            }
+        };
+        for cluster in &mut self.clusters {
+            absolute(&mut cluster.cluster.certificate_authority);
+        }
+        for user in &mut self.users {
+            absolute(&mut user.user.client_certificate);
+            absolute(&mut user.user.client_key);
+            absolute(&mut user.user.token_file);
+        }
+    }
+}
+
+/// Locates the kubeconfig files kubectl would load: an explicit
+/// `--kubeconfig`, else the `KUBECONFIG` path list, else `$HOME/.kube/config`.
+pub fn kubeconfig_paths(
+    explicit: Option<&str>,
+    kubeconfig_env: Option<OsString>,
+    home_dir: Option<PathBuf>,
+) -> Vec<PathBuf> {
+    if let Some(kubeconfig) = explicit {
+        return vec![PathBuf::from(kubeconfig)];
+    }
+    let from_env: Vec<PathBuf> = kubeconfig_env
+        .map(|value| {
+            std::env::split_paths(&value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !from_env.is_empty() {
+        return from_env;
+    }
+    home_dir
+        .map(|home| vec![home.join(".kube").join("config")])
+        .unwrap_or_default()
+}
+
+fn merge_named<T>(into: &mut Vec<T>, from: Vec<T>, name: impl Fn(&T) -> &String) {
+    for item in from {
+        if !into.iter().any(|existing| name(existing) == name(&item)) {
+            into.push(item);
+        }
+    }
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod kubeconfig_tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    const KUBECONFIG: &str = r#"
+apiVersion: v1
+kind: Config
+current-context: dev
+preferences: {}
+clusters:
+- name: dev-cluster
+  cluster:
+    server: https://dev.example.com:6443
+    certificate-authority-data: ZGV2LWNh
+- name: prod-cluster
+  cluster:
+    server: https://prod.example.com:6443
+    certificate-authority: certs/prod-ca.crt
+    extensions:
+    - name: client.authentication.k8s.io/exec
+      extension:
+        audience: prod
+contexts:
+- name: dev
+  context:
+    cluster: dev-cluster
+    user: dev-user
+    namespace: team
+- name: prod
+  context:
+    cluster: prod-cluster
+    user: prod-user
+- name: dangling
+  context:
+    cluster: nowhere
+users:
+- name: dev-user
+  user:
+    token: abc
+- name: prod-user
+  user:
+    exec:
+      apiVersion: client.authentication.k8s.io/v1beta1
+      command: aws
+"#;
+
+    #[test]
+    fn test_parse_and_resolve() {
+        let config = Kubeconfig::parse(KUBECONFIG, None).unwrap();
+        assert_eq!(config.current_context(), Some("dev"));
+
+        let dev = config.resolve(None).unwrap();
+        assert_eq!(dev.name, "dev");
+        assert_eq!(dev.context.namespace.as_deref(), Some("team"));
+        assert_eq!(
+            dev.cluster.server.as_deref(),
+            Some("https://dev.example.com:6443")
+        );
+        assert_eq!(dev.user.unwrap().token.as_deref(), Some("abc"));
+
+        let prod = config.resolve(Some("prod")).unwrap();
+        assert!(prod.user.unwrap().exec.is_some());
+        assert_eq!(prod.cluster.extensions.len(), 1);
+
+        assert!(matches!(
+            config.resolve(Some("missing")),
+            Err(KubeconfigError::ContextNotFound(_))
+        ));
+        assert!(matches!(
+            config.resolve(Some("dangling")),
+            Err(KubeconfigError::ClusterNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_empty_and_null_lists() {
+        assert_eq!(Kubeconfig::parse("", None).unwrap(), Kubeconfig::default());
+
+        let config = Kubeconfig::parse("clusters: null\ncontexts:\nusers: []\n", None).unwrap();
+        assert!(config.clusters.is_empty());
+        assert!(matches!(
+            config.resolve(None),
+            Err(KubeconfigError::NoCurrentContext)
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let contents = "clusters:\n- name: a\n  cluster:\n    server: [unterminated\n";
+        let err = Kubeconfig::parse(contents, Some(Path::new("/tmp/config"))).unwrap_err();
+        match &err {
+            KubeconfigError::Parse { line, .. } => assert!(line.is_some()),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(err.to_string().starts_with("/tmp/config:"), "{}", err);
+    }
+
+    #[test]
+    fn test_kubeconfig_paths() {
+        let home = Some(PathBuf::from("/home/user"));
+
+        assert_eq!(
+            kubeconfig_paths(None, None, home.clone()),
+            vec![PathBuf::from("/home/user/.kube/config")]
+        );
+        assert_eq!(
+            kubeconfig_paths(None, Some("/a::/b".into()), home.clone()),
+            vec![PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+        assert_eq!(
+            kubeconfig_paths(Some("/c"), Some("/a:/b".into()), home),
+            vec![PathBuf::from("/c")]
+        );
+    }
+
+    #[test]
+    fn test_load_merges_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = temp_dir.path().join("first");
+        let second = temp_dir.path().join("second");
+        fs::write(
+            &first,
+            r#"
+clusters:
+- name: dev-cluster
+  cluster:
+    server: https://first.example.com
+contexts:
+- name: dev
+  context:
+    cluster: dev-cluster
+"#,
+        )
+        .unwrap();
+        fs::write(&second, KUBECONFIG).unwrap();
+
+        let paths = vec![first, temp_dir.path().join("missing"), second];
+        let config = Kubeconfig::load(&paths).unwrap();
+
+        // current-context only comes from the second file, but the first
+        // definitions of dev and dev-cluster win
+        let dev = config.resolve(None).unwrap();
+        assert_eq!(dev.name, "dev");
+        assert_eq!(
+            dev.cluster.server.as_deref(),
+            Some("https://first.example.com")
+        );
+        assert!(dev.user.is_none());
+
+        // Relative paths are resolved against the file that declared them
+        let prod = config.resolve(Some("prod")).unwrap();
+        assert_eq!(
+            prod.cluster.certificate_authority.as_deref(),
+            Some(temp_dir.path().join("certs/prod-ca.crt").to_str().unwrap())
+        );
+
+        assert!(matches!(
+            Kubeconfig::load(&[temp_dir.path().join("missing")]),
+            Err(KubeconfigError::NotFound(_))
+        ));
+    }
+}
@@ -1 +1,124 @@
+pub mod cache;
 pub mod cli;
+pub mod download;
+pub mod fanout;
+pub mod kubeconfig;
+pub mod output;
+pub mod pins;
+pub mod probe;
+pub mod process;
+pub mod project;
+pub mod shim;
+pub mod store;
+pub mod version;
+
+/// Parses the environment variable `name`, treating unset or malformed values
+/// as absent.
+pub fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Reads the boolean environment variable `name`, accepting the usual
+/// spellings such as `1`, `yes` or `False`. Unset or empty is `default`, and
+/// anything else is an error rather than silently ignored.
+pub fn env_flag(name: &str, default: bool) -> anyhow::Result<bool> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => parse_flag(&value).ok_or_else(|| {
+            anyhow::anyhow!(
+                "invalid {} value {}, expected true or false",
+                name,
+                value.trim()
+            )
+        }),
+        _ => Ok(default),
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Tests that change the process environment, chiefly `HOME`, take
+/// [`test_support::env_lock`] so that parallel tests do not see each other's.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::ops::Deref;
+    use std::sync::{Mutex, MutexGuard};
+
+    use tempfile::TempDir;
+
+    use crate::shim::KorrectShimConfig;
+
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    pub fn env_lock() -> MutexGuard<'static, ()> {
+        // A test failing with the lock held leaves nothing to recover
+        ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A temporary `HOME`, holding the environment lock until dropped.
+    pub struct TempHome {
+        dir: TempDir,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Deref for TempHome {
+        type Target = TempDir;
+
+        fn deref(&self) -> &TempDir {
+            &self.dir
+        }
+    }
+
+    /// Points `HOME` at a temporary directory and clears the variables that
+    /// would change how the shim resolves, e.g. a developer's own
+    /// `KORRECT_KUBECTL_VERSION` or `KUBECONFIG`.
+    pub fn setup_temp_home() -> (TempHome, String) {
+        let lock = env_lock();
+        let dir = TempDir::new().unwrap();
+        let temp_home = dir.path().to_str().unwrap().to_owned();
+        std::env::set_var("HOME", &temp_home);
+        for (name, _) in std::env::vars_os() {
+            let name = name.to_string_lossy();
+            if name.starts_with("KORRECT_") || name == "KUBECONFIG" {
+                std::env::remove_var(name.as_ref());
+            }
+        }
+        (TempHome { dir, _lock: lock }, temp_home)
+    }
+
+    /// The shim's config for a test, ignoring any project version file above
+    /// the working directory, such as a `.tool-versions` of the checkout's.
+    pub fn test_config() -> KorrectShimConfig {
+        let mut config = KorrectShimConfig::new(false).unwrap();
+        config.project_dir = None;
+        config
+    }
+
+    pub fn remove_temp_home(home: TempHome) {
+        std::fs::remove_dir_all(home.path()).ok();
+    }
+}
+
+#[cfg(test)]
+mod lib_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flag() {
+        for value in ["1", "true", "True", " YES ", "on"] {
+            assert_eq!(parse_flag(value), Some(true), "{}", value);
+        }
+        for value in ["0", "false", "FALSE", "no", "Off"] {
+            assert_eq!(parse_flag(value), Some(false), "{}", value);
+        }
+        assert_eq!(parse_flag("maybe"), None);
+        assert_eq!(parse_flag("2"), None);
+    }
+}
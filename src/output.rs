@@ -0,0 +1,119 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+
+/// Prints `value` as JSON or YAML, returning false for the table format,
+/// which every command lays out itself.
+pub fn print_structured<T: Serialize>(format: OutputFormat, value: &T) -> Result<bool> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        OutputFormat::Table => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Lays out `rows` in left-aligned columns under upper-case `headers`.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = headers.iter().map(|header| header.to_uppercase()).collect();
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Formats a byte count with binary units, e.g. `48.2 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats a duration in seconds as a short age such as `45s`, `12m`, `5h`
+/// or `3d`.
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3_599 => format!("{}m", secs / 60),
+        3_600..=86_399 => format!("{}h", secs / 3_600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+/// Formats seconds since the Unix epoch as a UTC `YYYY-MM-DD HH:MM` date.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60
+    )
+}
+
+#[cfg(test)]
+mod output_tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let rows = vec![
+            vec!["v1.29.3".to_owned(), "dev, prod".to_owned()],
+            vec!["v1.30.10".to_owned(), "".to_owned()],
+        ];
+        assert_eq!(
+            table(&["version", "contexts"], &rows),
+            "VERSION   CONTEXTS\nv1.29.3   dev, prod\nv1.30.10\n"
+        );
+    }
+
+    #[test]
+    fn test_format_size_age_and_timestamp() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(50_577_560), "48.2 MiB");
+        assert_eq!(format_age(59), "59s");
+        assert_eq!(format_age(3_600), "1h");
+        assert_eq!(format_age(200_000), "2d");
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1_709_251_199), "2024-02-29 23:59");
+        assert_eq!(format_timestamp(1_735_689_600), "2025-01-01 00:00");
+    }
+}
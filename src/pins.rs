@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A kubectl version pinned to a context with `korrect pin`, run for it
+/// without consulting the cache or probing the cluster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pin {
+    /// The kubectl release the spec resolved to when pinned
+    pub version: String,
+    /// The version spec as given, e.g. `1.27` or `stable`
+    pub spec: String,
+    /// Seconds since the Unix epoch
+    pub pinned_at: u64,
+}
+
+/// Pins by context name, stored as `pins.json` in the config directory.
+pub type Pins = BTreeMap<String, Pin>;
+
+pub fn pins_file(config_dir: &Path) -> PathBuf {
+    config_dir.join("pins.json")
+}
+
+/// Reads the pins in `config_dir`; having none is not an error.
+pub fn read_pins(config_dir: &Path) -> Result<Pins> {
+    let file = pins_file(config_dir);
+    match fs::read_to_string(&file) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", file.display())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Pins::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", file.display())),
+    }
+}
+
+pub fn write_pins(config_dir: &Path, pins: &Pins) -> Result<()> {
+    fs::create_dir_all(config_dir)?;
+    let file = pins_file(config_dir);
+    fs::write(&file, serde_json::to_string_pretty(pins)?)
+        .with_context(|| format!("failed to write {}", file.display()))
+}
+
+#[cfg(test)]
+mod pins_tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pins_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join("korrect");
+        assert!(read_pins(&config_dir).unwrap().is_empty());
+
+        let mut pins = Pins::new();
+        pins.insert(
+            "prod".to_owned(),
+            Pin {
+                version: "v1.27.16".to_owned(),
+                spec: "1.27".to_owned(),
+                pinned_at: 1_700_000_000,
+            },
+        );
+        write_pins(&config_dir, &pins).unwrap();
+        assert_eq!(read_pins(&config_dir).unwrap(), pins);
+
+        fs::write(pins_file(&config_dir), "not json").unwrap();
+        assert!(read_pins(&config_dir).is_err());
+    }
+}
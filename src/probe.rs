@@ -0,0 +1,307 @@
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::blocking::Client;
+use reqwest::header::HOST;
+use reqwest::{Certificate, Identity, Proxy, StatusCode, Url};
+use serde_json::Value;
+
+use crate::kubeconfig::{Cluster, User};
+
+#[derive(Debug)]
+pub enum ProbeError {
+    /// The user authenticates through an exec plugin or auth provider and the
+    /// server refused the anonymous request, so only kubectl can probe it.
+    UnsupportedAuth,
+    /// The cluster combines TLS settings korrect cannot honour itself, such as
+    /// a `tls-server-name` behind a proxy.
+    UnsupportedTls,
+    /// The server rejected the credentials korrect sent.
+    Unauthorized(StatusCode),
+    Http(StatusCode),
+    Request(reqwest::Error),
+    InvalidConfig(String),
+    InvalidResponse(String),
+}
+
+impl ProbeError {
+    /// Whether spawning kubectl, with its full authentication support, might
+    /// succeed where the native prober did not.
+    pub fn needs_kubectl(&self) -> bool {
+        matches!(
+            self,
+            ProbeError::UnsupportedAuth | ProbeError::UnsupportedTls | ProbeError::Unauthorized(_)
+        )
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::UnsupportedAuth => {
+                write!(f, "the user's authentication method requires kubectl")
+            }
+            ProbeError::UnsupportedTls => {
+                write!(f, "the cluster's TLS settings require kubectl")
+            }
+            ProbeError::Unauthorized(status) => {
+                write!(f, "the API server rejected the credentials ({})", status)
+            }
+            ProbeError::Http(status) => write!(f, "the API server returned {}", status),
+            ProbeError::Request(e) => write!(f, "request to the API server failed: {}", e),
+            ProbeError::InvalidConfig(message) => write!(f, "invalid kubeconfig: {}", message),
+            ProbeError::InvalidResponse(message) => {
+                write!(f, "unexpected /version response: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProbeError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProbeError {
+    fn from(e: reqwest::Error) -> Self {
+        ProbeError::Request(e)
+    }
+}
+
+/// Asks the API server of `cluster` for its `gitVersion` via `GET /version`,
+/// authenticating with the token, basic auth or client certificate of `user`.
+pub fn probe_server_version(
+    cluster: &Cluster,
+    user: Option<&User>,
+    timeout: Duration,
+) -> Result<String, ProbeError> {
+    let server = cluster
+        .server
+        .as_deref()
+        .ok_or_else(|| ProbeError::InvalidConfig("cluster has no server".to_owned()))?;
+    let mut url = Url::parse(&format!("{}/version", server.trim_end_matches('/')))
+        .map_err(|e| ProbeError::InvalidConfig(format!("{}: {}", server, e)))?;
+
+    let mut builder = Client::builder()
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .danger_accept_invalid_certs(cluster.insecure_skip_tls_verify);
+    if let Some(ca) = read_data(
+        &cluster.certificate_authority_data,
+        &cluster.certificate_authority,
+    )? {
+        builder = builder.tls_built_in_root_certs(false);
+        for cert in Certificate::from_pem_bundle(&ca)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(proxy_url) = &cluster.proxy_url {
+        builder = builder.proxy(Proxy::all(proxy_url)?);
+    }
+
+    // Like kubectl, connect to the server's address but present and verify
+    // `tls-server-name`, by requesting that name resolved to the address
+    let mut host_header = None;
+    if let Some(name) = cluster
+        .tls_server_name
+        .as_deref()
+        .filter(|name| !name.is_empty())
+    {
+        if cluster.proxy_url.is_some() {
+            return Err(ProbeError::UnsupportedTls);
+        }
+        let addrs = url
+            .socket_addrs(|| None)
+            .map_err(|e| ProbeError::InvalidConfig(format!("{}: {}", server, e)))?;
+        let host = url.host_str().unwrap_or_default();
+        host_header = Some(match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        });
+        builder = builder.resolve_to_addrs(name, &addrs);
+        url.set_host(Some(name))
+            .map_err(|e| ProbeError::InvalidConfig(format!("tls-server-name {}: {}", name, e)))?;
+    }
+
+    let mut plugin_auth = false;
+    let mut bearer_token = None;
+    let mut basic_auth = None;
+    if let Some(user) = user {
+        plugin_auth = user.exec.is_some() || user.auth_provider.is_some();
+        let cert = read_data(&user.client_certificate_data, &user.client_certificate)?;
+        let key = read_data(&user.client_key_data, &user.client_key)?;
+        if let (Some(mut cert), Some(key)) = (cert, key) {
+            cert.push(b'\n');
+            cert.extend_from_slice(&key);
+            builder = builder.identity(Identity::from_pem(&cert)?);
+        }
+        bearer_token = match (&user.token, &user.token_file) {
+            (Some(token), _) => Some(token.clone()),
+            (None, Some(file)) => Some(
+                fs::read_to_string(file)
+                    .map_err(|e| ProbeError::InvalidConfig(format!("{}: {}", file, e)))?
+                    .trim()
+                    .to_owned(),
+            ),
+            (None, None) => None,
+        };
+        if let Some(username) = &user.username {
+            basic_auth = Some((username.clone(), user.password.clone()));
+        }
+    }
+
+    let client = builder.build()?;
+    let mut request = client.get(url);
+    if let Some(host) = host_header {
+        request = request.header(HOST, host);
+    }
+    if let Some(token) = bearer_token {
+        request = request.bearer_auth(token);
+    } else if let Some((username, password)) = basic_auth {
+        request = request.basic_auth(username, password);
+    }
+
+    let response = request.send()?;
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err(if plugin_auth {
+            ProbeError::UnsupportedAuth
+        } else {
+            ProbeError::Unauthorized(status)
+        });
+    }
+    if !status.is_success() {
+        return Err(ProbeError::Http(status));
+    }
+
+    let json: Value = response.json()?;
+    json["gitVersion"]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| ProbeError::InvalidResponse("missing gitVersion".to_owned()))
+}
+
+/// Returns the inline base64 `data` if set, else the contents of `file`.
+fn read_data(data: &Option<String>, file: &Option<String>) -> Result<Option<Vec<u8>>, ProbeError> {
+    if let Some(data) = data {
+        let decoded = BASE64_STANDARD
+            .decode(data.trim())
+            .map_err(|e| ProbeError::InvalidConfig(e.to_string()))?;
+        return Ok(Some(decoded));
+    }
+    if let Some(file) = file {
+        let contents =
+            fs::read(file).map_err(|e| ProbeError::InvalidConfig(format!("{}: {}", file, e)))?;
+        return Ok(Some(contents));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod probe_tests {
+    use super::*;
+
+    const VERSION: &str = r#"{"major":"1","minor":"29","gitVersion":"v1.29.3-eks-adc7111"}"#;
+
+    fn cluster(server: &str) -> Cluster {
+        Cluster {
+            server: Some(server.to_owned()),
+            ..Cluster::default()
+        }
+    }
+
+    #[test]
+    fn test_probe_anonymous() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/version")
+            .with_status(200)
+            .with_body(VERSION)
+            .create();
+
+        let version =
+            probe_server_version(&cluster(&server.url()), None, Duration::from_secs(5)).unwrap();
+        assert_eq!(version, "v1.29.3-eks-adc7111");
+        mock.assert();
+    }
+
+    #[test]
+    fn test_probe_with_token() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/version")
+            .match_header("authorization", "Bearer s3cr3t")
+            .with_status(200)
+            .with_body(VERSION)
+            .create();
+
+        let user = User {
+            token: Some("s3cr3t".to_owned()),
+            ..User::default()
+        };
+        let version =
+            probe_server_version(&cluster(&server.url()), Some(&user), Duration::from_secs(5))
+                .unwrap();
+        assert_eq!(version, "v1.29.3-eks-adc7111");
+        mock.assert();
+    }
+
+    #[test]
+    fn test_probe_unauthorized() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/version").with_status(401).create();
+
+        let err = probe_server_version(&cluster(&server.url()), None, Duration::from_secs(5))
+            .unwrap_err();
+        assert!(matches!(err, ProbeError::Unauthorized(_)));
+        assert!(err.needs_kubectl());
+
+        let user = User {
+            exec: Some(serde_yaml::from_str("command: aws").unwrap()),
+            ..User::default()
+        };
+        let err =
+            probe_server_version(&cluster(&server.url()), Some(&user), Duration::from_secs(5))
+                .unwrap_err();
+        assert!(matches!(err, ProbeError::UnsupportedAuth));
+    }
+
+    #[test]
+    fn test_probe_tls_server_name() {
+        let mut server = mockito::Server::new();
+        let host = server.host_with_port();
+        let mock = server
+            .mock("GET", "/version")
+            .match_header("host", host.as_str())
+            .with_status(200)
+            .with_body(VERSION)
+            .create();
+
+        // The name is requested, but the connection goes to the server
+        let mut cluster = cluster(&server.url());
+        cluster.tls_server_name = Some("api.cluster.internal".to_owned());
+        let version = probe_server_version(&cluster, None, Duration::from_secs(5)).unwrap();
+        assert_eq!(version, "v1.29.3-eks-adc7111");
+        mock.assert();
+
+        cluster.proxy_url = Some("http://127.0.0.1:9".to_owned());
+        let err = probe_server_version(&cluster, None, Duration::from_secs(5)).unwrap_err();
+        assert!(matches!(err, ProbeError::UnsupportedTls));
+        assert!(err.needs_kubectl());
+    }
+
+    #[test]
+    fn test_probe_unreachable() {
+        let err =
+            probe_server_version(&cluster("http://127.0.0.1:9"), None, Duration::from_secs(5))
+                .unwrap_err();
+        assert!(matches!(err, ProbeError::Request(_)));
+        assert!(!err.needs_kubectl());
+    }
+}
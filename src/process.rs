@@ -0,0 +1,199 @@
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command as ProcessCommand, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A command running the kubectl binary at `path`, presented to it as
+/// `kubectl` rather than under its versioned file name.
+pub fn kubectl_command(path: &Path) -> ProcessCommand {
+    #[allow(unused_mut)]
+    let mut command = ProcessCommand::new(path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.arg0("kubectl");
+    }
+    command
+}
+
+/// Replaces this process with kubectl, so that it receives signals, owns the
+/// terminal and reports its exit status directly. Only returns on failure.
+#[cfg(unix)]
+pub fn exec_kubectl(path: &Path, args: &[String]) -> io::Error {
+    use std::os::unix::process::CommandExt;
+    kubectl_command(path).args(args).exec()
+}
+
+/// The exit code to pass on for a child's status, following the shell
+/// convention of 128+N for a child killed by signal N.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Spawns `command` and waits for it, passing signals sent to this process
+/// on to the child while it runs.
+pub fn run_forwarding_signals(command: &mut ProcessCommand) -> io::Result<ExitStatus> {
+    let mut child = command.spawn()?;
+    let _forwarding = ForwardSignals::to(&child);
+    child.wait()
+}
+
+/// Waits for `child` until `timeout` has passed, then kills it. Returns
+/// `None` for a child that had to be killed.
+pub fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Forwards termination and user signals to a child for as long as it lives,
+/// restoring their default handling when dropped. This covers signals sent to
+/// korrect alone, e.g. by a supervisor or `timeout`.
+///
+/// SIGINT and SIGQUIT are ignored instead of forwarded: they come from the
+/// terminal, which already delivers them to the whole foreground process
+/// group. Forwarding them would hand kubectl a second Ctrl-C, cutting short
+/// the graceful shutdown of `port-forward` or reaching an `exec -it` session
+/// twice, while korrect itself must outlive the child to pass on its status.
+pub struct ForwardSignals {
+    _private: (),
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    pub const FORWARDED: [libc::c_int; 4] =
+        [libc::SIGHUP, libc::SIGTERM, libc::SIGUSR1, libc::SIGUSR2];
+
+    /// Left to the terminal, which sends them to the child itself
+    pub const IGNORED: [libc::c_int; 2] = [libc::SIGINT, libc::SIGQUIT];
+
+    pub static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn forward(signal: libc::c_int) {
+        let pid = CHILD_PID.load(Ordering::SeqCst);
+        if pid > 0 {
+            // kill is async-signal-safe
+            unsafe {
+                libc::kill(pid, signal);
+            }
+        }
+    }
+
+    pub fn set_handler(forwarding: bool) {
+        let (forwarded, ignored) = if forwarding {
+            let forward = forward as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            (forward, libc::SIG_IGN)
+        } else {
+            (libc::SIG_DFL, libc::SIG_DFL)
+        };
+        for signal in FORWARDED {
+            set_action(signal, forwarded);
+        }
+        for signal in IGNORED {
+            set_action(signal, ignored);
+        }
+    }
+
+    fn set_action(signal: libc::c_int, handler: libc::sighandler_t) {
+        // SAFETY: the action is fully initialised before use and the handler
+        // only calls async-signal-safe functions
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+impl ForwardSignals {
+    #[cfg(unix)]
+    pub fn to(child: &Child) -> Self {
+        use std::sync::atomic::Ordering;
+        signals::CHILD_PID.store(child.id() as i32, Ordering::SeqCst);
+        signals::set_handler(true);
+        ForwardSignals { _private: () }
+    }
+
+    #[cfg(not(unix))]
+    pub fn to(_child: &Child) -> Self {
+        ForwardSignals { _private: () }
+    }
+}
+
+impl Drop for ForwardSignals {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            use std::sync::atomic::Ordering;
+            signals::set_handler(false);
+            signals::CHILD_PID.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod process_tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_maps_signals() {
+        let status = ProcessCommand::new("sh")
+            .args(["-c", "exit 7"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_code(status), 7);
+
+        let status = ProcessCommand::new("sh")
+            .args(["-c", "kill -TERM $$"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_code(status), 128 + libc::SIGTERM);
+    }
+
+    #[test]
+    fn test_wait_timeout_kills_at_deadline() {
+        let mut child = ProcessCommand::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let status = wait_timeout(&mut child, Duration::from_secs(5)).unwrap();
+        assert_eq!(status.map(exit_code), Some(3));
+
+        let started = Instant::now();
+        let mut child = ProcessCommand::new("sleep").arg("10").spawn().unwrap();
+        assert!(wait_timeout(&mut child, Duration::from_millis(100))
+            .unwrap()
+            .is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_kubectl_command_sets_argv0() {
+        let output = kubectl_command(Path::new("/bin/sh"))
+            .args(["-c", "echo $0"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "kubectl");
+    }
+}
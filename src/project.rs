@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// A project file naming the kubectl version for its directory tree.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionFile {
+    pub path: PathBuf,
+    /// The version spec as written, e.g. `1.27` or `v1.27.3`
+    pub spec: String,
+}
+
+/// Files checked in every directory, in order of precedence.
+const VERSION_FILES: [&str; 3] = [".kubectl-version", ".tool-versions", ".korrect.toml"];
+
+/// Walks up from `dir` to the root and returns the nearest file that names a
+/// kubectl version. Files without one, such as a `.tool-versions` listing only
+/// other tools, are passed over.
+pub fn find_version_file(dir: &Path) -> Option<VersionFile> {
+    dir.ancestors().find_map(|dir| {
+        VERSION_FILES.iter().find_map(|name| {
+            let path = dir.join(name);
+            let contents = fs::read_to_string(&path).ok()?;
+            let spec = match *name {
+                ".kubectl-version" => parse_kubectl_version(&contents),
+                ".tool-versions" => parse_tool_versions(&contents),
+                _ => {
+                    let (spec, unknown) = parse_korrect_toml(&contents);
+                    for name in unknown {
+                        eprintln!("korrect: ignoring unknown {} in {}", name, path.display());
+                    }
+                    spec
+                }
+            }?;
+            Some(VersionFile { path, spec })
+        })
+    })
+}
+
+/// The first non-empty line that is not a comment.
+fn parse_kubectl_version(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .map(strip_comment)
+        .find(|line| !line.is_empty())
+        .map(str::to_owned)
+}
+
+/// The first version of the asdf `kubectl` line, e.g. `kubectl 1.27.3`.
+fn parse_tool_versions(contents: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let mut fields = strip_comment(line).split_whitespace();
+        (fields.next() == Some("kubectl"))
+            .then(|| fields.next())
+            .flatten()
+            .map(str::to_owned)
+    })
+}
+
+/// The `version` key of the `[kubectl]` table, along with the tables and
+/// keys korrect does not know, so that a typo is not silently passed over.
+/// Only the flat `key = "value"` subset of TOML is understood.
+fn parse_korrect_toml(contents: &str) -> (Option<String>, Vec<String>) {
+    let mut table: Option<&str> = None;
+    let mut version = None;
+    let mut unknown = Vec::new();
+    for line in contents.lines() {
+        let line = strip_comment(line);
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.trim();
+            if name != "kubectl" {
+                unknown.push(format!("table [{}]", name));
+            }
+            table = Some(name);
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match (table, key.trim()) {
+            (Some("kubectl"), "version") => {
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                version = version.or((!value.is_empty()).then(|| value.to_owned()));
+            }
+            (Some("kubectl"), key) => unknown.push(format!("key kubectl.{}", key)),
+            (None, key) => unknown.push(format!("key {}", key)),
+            // Reported with its table
+            (Some(_), _) => {}
+        }
+    }
+    (version, unknown)
+}
+
+/// The line without its trailing comment. A `#` inside a quoted value is
+/// part of the value.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return line[..i].trim(),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            _ => {}
+        }
+    }
+    line.trim()
+}
+
+#[cfg(test)]
+mod project_tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_version_files() {
+        assert_eq!(
+            parse_kubectl_version("# pinned for the migration\n\nv1.27.3\n").as_deref(),
+            Some("v1.27.3")
+        );
+        assert_eq!(parse_kubectl_version("\n"), None);
+
+        assert_eq!(
+            parse_tool_versions("nodejs 20.1.0\nkubectl 1.27.3 1.28.0 # ci\n").as_deref(),
+            Some("1.27.3")
+        );
+        assert_eq!(parse_tool_versions("nodejs 20.1.0\nkubectl\n"), None);
+
+        let toml = r#"
+[helm]
+version = "3.14.0"
+
+[kubectl]
+# scripts rely on the old --prune behaviour
+version = "1.27" # latest patch
+"#;
+        assert_eq!(
+            parse_korrect_toml(toml),
+            (Some("1.27".to_owned()), vec!["table [helm]".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_comments_inside_quotes_are_kept() {
+        assert_eq!(
+            strip_comment(r#"version = "1.27#rc" # note"#),
+            r#"version = "1.27#rc""#
+        );
+        assert_eq!(strip_comment("name = 'a # b'"), "name = 'a # b'");
+        assert_eq!(strip_comment("v1.27.3 # pinned"), "v1.27.3");
+        assert_eq!(
+            parse_korrect_toml("[kubectl]\nversion = \"v1.27.3\" # \"quoted\"\n").0,
+            Some("v1.27.3".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_korrect_toml_reports_unknown_keys() {
+        let toml = r#"
+channel = "stable"
+
+[kubectl]
+verison = "1.27"
+
+[helm]
+version = "3.14.0"
+"#;
+        assert_eq!(
+            parse_korrect_toml(toml),
+            (
+                None,
+                vec![
+                    "key channel".to_owned(),
+                    "key kubectl.verison".to_owned(),
+                    "table [helm]".to_owned()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_version_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        let nested = repo.join("deploy").join("prod");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_version_file(&nested), None);
+
+        fs::write(
+            repo.join(".korrect.toml"),
+            "[kubectl]\nversion = \"1.28\"\n",
+        )
+        .unwrap();
+        fs::write(repo.join(".tool-versions"), "nodejs 20.1.0\n").unwrap();
+        let found = find_version_file(&nested).unwrap();
+        assert_eq!(found.path, repo.join(".korrect.toml"));
+        assert_eq!(found.spec, "1.28");
+
+        // The nearest file wins, and .kubectl-version first within a directory
+        fs::write(repo.join(".kubectl-version"), "1.27.3\n").unwrap();
+        fs::write(nested.join(".tool-versions"), "kubectl 1.29.1\n").unwrap();
+        assert_eq!(find_version_file(&nested).unwrap().spec, "1.29.1");
+        assert_eq!(find_version_file(&repo).unwrap().spec, "1.27.3");
+    }
+}
@@ -0,0 +1,2667 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, ExitStatus, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::cache::{read_cache_entry, unix_now, CacheEntry};
+use crate::download::{detect_cpu_arch, detect_os, DownloadOptions};
+use crate::kubeconfig::{kubeconfig_paths, Cluster, Kubeconfig, KubeconfigError, User};
+use crate::pins::{read_pins, Pin};
+use crate::probe;
+use crate::process::{
+    exit_code, kubectl_command, run_forwarding_signals, wait_timeout, ForwardSignals,
+};
+use crate::project::{find_version_file, VersionFile};
+use crate::store::KubectlStore;
+use crate::version::KubeVersion;
+use crate::{env_flag, env_parse};
+
+/// What to run when the server version cannot be probed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FallbackPolicy {
+    /// The version last probed for the cluster, else `NewestInstalled`
+    #[default]
+    LastKnown,
+    /// The newest installed kubectl, else `Stable`
+    NewestInstalled,
+    /// The current stable release
+    Stable,
+    /// Refuse to run kubectl
+    Fail,
+}
+
+impl std::str::FromStr for FallbackPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "last-known" => Ok(FallbackPolicy::LastKnown),
+            "newest-installed" => Ok(FallbackPolicy::NewestInstalled),
+            "stable" => Ok(FallbackPolicy::Stable),
+            "fail" => Ok(FallbackPolicy::Fail),
+            other => Err(anyhow!(
+                "unknown fallback policy {}, expected one of last-known, newest-installed, stable, fail",
+                other
+            )),
+        }
+    }
+}
+
+/// What decided the kubectl version for a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionSource {
+    /// The server version cached by an earlier probe
+    Cache,
+    /// A probe of the server made for this command
+    Probe,
+    /// The fallback policy, because the server could not be probed
+    Fallback,
+    /// A version pinned to the context with `korrect pin`
+    Pin,
+    /// A version file in the working directory or one of its parents
+    ProjectFile,
+    /// The `KORRECT_KUBECTL_VERSION` environment variable
+    Env,
+    /// The name the shim was invoked by, e.g. `kubectl@1.27`
+    ProgramName,
+    /// The `--version` flag of `korrect exec`
+    Flag,
+}
+
+impl VersionSource {
+    /// Whether the version was chosen for the cluster rather than derived
+    /// from it, leaving the cache alone.
+    pub fn is_override(&self) -> bool {
+        !matches!(
+            self,
+            VersionSource::Cache | VersionSource::Probe | VersionSource::Fallback
+        )
+    }
+}
+
+impl fmt::Display for VersionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionSource::Cache => write!(f, "cache entry"),
+            VersionSource::Probe => write!(f, "fresh probe"),
+            VersionSource::Fallback => write!(f, "fallback"),
+            VersionSource::Pin => write!(f, "pin"),
+            VersionSource::ProjectFile => write!(f, "project file"),
+            VersionSource::Env => write!(f, "KORRECT_KUBECTL_VERSION"),
+            VersionSource::ProgramName => write!(f, "program name"),
+            VersionSource::Flag => write!(f, "--version flag"),
+        }
+    }
+}
+
+/// The kubectl chosen for a command and how it was chosen.
+#[derive(Debug, Clone, Serialize)]
+pub struct Resolution {
+    /// The kubectl release to run
+    pub version: String,
+    pub path: PathBuf,
+    pub installed: bool,
+    pub source: VersionSource,
+    /// The server's version as a kubectl release, unless it could not be determined
+    pub server_version: Option<String>,
+    pub context: String,
+    pub server: String,
+    pub cache_file: PathBuf,
+    /// The cluster's cache entry after resolution
+    pub cache_entry: Option<CacheEntry>,
+    /// The context's pin, when it decided the version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin: Option<Pin>,
+    /// The project file, when it decided the version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_file: Option<VersionFile>,
+}
+
+/// How far a kubectl may be from the server in minor versions; kubectl
+/// supports one minor version older or newer than the API server.
+pub const SUPPORTED_SKEW: u64 = 1;
+
+/// Which kubectl to run for a server version.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SelectionPolicy {
+    /// The kubectl release matching the server's patch version
+    #[default]
+    Exact,
+    /// The newest patch release of the server's minor version
+    LatestPatchOfMinor,
+    /// Any installed kubectl within the supported skew, else `Exact`
+    ReuseCompatible,
+}
+
+impl std::str::FromStr for SelectionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "exact" => Ok(SelectionPolicy::Exact),
+            "latest-patch-of-minor" => Ok(SelectionPolicy::LatestPatchOfMinor),
+            "reuse-compatible" => Ok(SelectionPolicy::ReuseCompatible),
+            other => Err(anyhow!(
+                "unknown selection policy {}, expected one of exact, latest-patch-of-minor, reuse-compatible",
+                other
+            )),
+        }
+    }
+}
+
+/// kubectl's global flags that change which cluster a command talks to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionFlags {
+    pub kubeconfig: Option<String>,
+    pub context: Option<String>,
+    pub cluster: Option<String>,
+    pub server: Option<String>,
+}
+
+impl ConnectionFlags {
+    /// Picks the connection flags out of a kubectl argv in both the
+    /// `--flag=value` and `--flag value` forms. Everything after `--` belongs
+    /// to the command being run (e.g. by `kubectl exec`) and is ignored.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Self {
+        let mut flags = Self::default();
+        let mut args = args.iter().map(AsRef::as_ref);
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            }
+
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with('-') => (name, Some(value)),
+                _ => (arg, None),
+            };
+            let slot = match name {
+                "--kubeconfig" => &mut flags.kubeconfig,
+                "--context" => &mut flags.context,
+                "--cluster" => &mut flags.cluster,
+                "--server" | "-s" => &mut flags.server,
+                _ if name.starts_with("-s")
+                    && !name.starts_with("--")
+                    && inline_value.is_none() =>
+                {
+                    // pflag also accepts the shorthand glued to its value: -shttps://...
+                    flags.server = Some(name[2..].to_owned());
+                    continue;
+                }
+                _ => continue,
+            };
+            let value = match inline_value {
+                Some(value) => Some(value.to_owned()),
+                None => args.next().map(str::to_owned),
+            };
+            if value.is_some() {
+                *slot = value;
+            }
+        }
+
+        flags
+    }
+
+    /// The flags to hand to a probing kubectl so that it talks to the same
+    /// cluster as the command being run.
+    pub fn to_args(&self) -> Vec<String> {
+        [
+            ("--kubeconfig", &self.kubeconfig),
+            ("--context", &self.context),
+            ("--cluster", &self.cluster),
+            ("--server", &self.server),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+        .collect()
+    }
+}
+
+/// The cluster a kubectl invocation talks to, as resolved from the kubeconfig.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterTarget {
+    pub context: String,
+    pub server: String,
+    ca_identity: String,
+    /// The cluster with any `--server` override applied, used for probing
+    cluster: Cluster,
+    user: Option<User>,
+}
+
+impl ClusterTarget {
+    fn from_kubeconfig(config: &Kubeconfig, flags: &ConnectionFlags) -> Result<Self> {
+        let context = config.resolve(flags.context.as_deref());
+        let user = context
+            .as_ref()
+            .ok()
+            .and_then(|context| context.user.cloned());
+        let (context_name, cluster) = match &flags.cluster {
+            // An explicit --cluster only needs the context for its name
+            Some(name) => {
+                let context_name = match &context {
+                    Ok(context) => context.name.to_owned(),
+                    Err(_) => flags.context.clone().unwrap_or_default(),
+                };
+                let cluster = config
+                    .cluster(name)
+                    .ok_or_else(|| KubeconfigError::ClusterNotFound(name.to_owned()))?;
+                (context_name, cluster)
+            }
+            None => {
+                let context = context?;
+                (context.name.to_owned(), context.cluster)
+            }
+        };
+
+        let server = match (&flags.server, &cluster.server) {
+            (Some(server), _) | (None, Some(server)) => server.to_owned(),
+            (None, None) => return Err(anyhow!("cluster for {} has no server", context_name)),
+        };
+        let ca_identity = if let Some(data) = &cluster.certificate_authority_data {
+            format!("data:{:x}", Sha256::digest(data.trim().as_bytes()))
+        } else if let Some(file) = &cluster.certificate_authority {
+            format!("file:{}", file)
+        } else if cluster.insecure_skip_tls_verify {
+            "insecure".to_owned()
+        } else {
+            "system".to_owned()
+        };
+
+        Ok(Self {
+            context: context_name,
+            cluster: Cluster {
+                server: Some(server.clone()),
+                ..cluster.clone()
+            },
+            server,
+            ca_identity,
+            user,
+        })
+    }
+
+    /// Contexts pointing at the same API server with the same CA share a key,
+    /// no matter which kubeconfig file or context name they came from.
+    fn cache_key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.server.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.ca_identity.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+pub struct KorrectShimConfig {
+    pub korrect_config_path: PathBuf,
+    pub korrect_cache_path: PathBuf,
+    pub korrect_bin_path: PathBuf,
+    pub dl_url: String,
+    pub download_options: DownloadOptions,
+    pub probe_timeout: Duration,
+    pub fallback_policy: FallbackPolicy,
+    pub selection_policy: SelectionPolicy,
+    pub strict_skew: bool,
+    /// Watch kubectl's stderr for its version skew warning
+    pub watch_skew: bool,
+    /// Re-run read-only commands when the skew warning shows up
+    pub rerun_on_skew: bool,
+    /// How long a cached server version is trusted before it is re-validated
+    /// in the background, zero to trust it forever
+    pub cache_ttl: Duration,
+    /// Where the search for project version files starts, normally the
+    /// working directory
+    pub project_dir: Option<PathBuf>,
+    /// Version spec from `KORRECT_KUBECTL_VERSION`, overriding everything but
+    /// `program_version`
+    pub env_version: Option<String>,
+    /// Version spec from the name the shim was invoked by, see `program_version`
+    pub program_version: Option<String>,
+    /// Version spec given to `korrect exec --version`, overriding everything
+    pub flag_version: Option<String>,
+    /// Download missing kubectl versions instead of failing
+    pub auto_download: bool,
+    pub os: String,
+    pub cpu_arch: String,
+    pub debug: bool,
+}
+
+impl KorrectShimConfig {
+    pub fn new(debug: bool) -> Result<Self> {
+        let dl_url = env::var("KORRECT_BASE_URL").unwrap_or("https://dl.k8s.io".to_owned());
+        let home_dir = dirs::home_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Home directory not found")
+        })?;
+        let config_dir: PathBuf = dirs::config_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Config directory not found")
+        })?;
+        let cache_dir: PathBuf = dirs::cache_dir().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Cache directory not found")
+        })?;
+        let korrect_config_path = config_dir.join("korrect");
+        let korrect_cache_path = cache_dir.join("korrect");
+        let korrect_bin_path = home_dir.join(".korrect").join("bin");
+
+        fs::create_dir_all(&korrect_cache_path).ok();
+        fs::create_dir_all(&korrect_bin_path).ok();
+
+        let os = detect_os();
+        let cpu_arch = detect_cpu_arch();
+
+        let probe_timeout = Duration::from_secs(env_parse("KORRECT_PROBE_TIMEOUT").unwrap_or(5));
+        let fallback_policy = match env::var("KORRECT_FALLBACK") {
+            Ok(policy) => policy.parse()?,
+            Err(_) => FallbackPolicy::default(),
+        };
+        let selection_policy = match env::var("KORRECT_SELECTION") {
+            Ok(policy) => policy.parse()?,
+            Err(_) => SelectionPolicy::default(),
+        };
+        let strict_skew = env_flag("KORRECT_STRICT_SKEW", false)?;
+        let rerun_on_skew = env_flag("KORRECT_RERUN_ON_SKEW", false)?;
+        let watch_skew = env_flag("KORRECT_WATCH_SKEW", false)? || rerun_on_skew;
+        let cache_ttl = Duration::from_secs(env_parse("KORRECT_CACHE_TTL").unwrap_or(86_400));
+
+        Ok(Self {
+            korrect_config_path,
+            korrect_cache_path,
+            korrect_bin_path,
+            os,
+            cpu_arch,
+            debug,
+            dl_url,
+            download_options: DownloadOptions::from_env(),
+            probe_timeout,
+            fallback_policy,
+            selection_policy,
+            strict_skew,
+            watch_skew,
+            rerun_on_skew,
+            cache_ttl,
+            project_dir: env::current_dir().ok(),
+            env_version: env::var("KORRECT_KUBECTL_VERSION")
+                .ok()
+                .filter(|spec| !spec.trim().is_empty()),
+            program_version: None,
+            flag_version: None,
+            auto_download: env_flag("KORRECT_AUTO_DOWNLOAD", true)?,
+        })
+    }
+    fn get_current_stable_version(&self) -> Result<String> {
+        Ok(self.store().marker_version("stable")?.tag())
+    }
+
+    /// The kubectl store backed by the bin directory and download settings.
+    pub fn store(&self) -> KubectlStore {
+        KubectlStore {
+            bin_path: self.korrect_bin_path.clone(),
+            dl_url: self.dl_url.clone(),
+            os: self.os.clone(),
+            cpu_arch: self.cpu_arch.clone(),
+            download_options: self.download_options.clone(),
+        }
+    }
+
+    fn get_server_version(
+        &self,
+        target: &ClusterTarget,
+        flags: &ConnectionFlags,
+    ) -> Result<(String, VersionSource)> {
+        let cache_file = self.get_version_cache_file(target);
+
+        if self.debug {
+            eprintln!(
+                "cache_file for context [{}] on [{}] is [{:#?}].",
+                &target.context,
+                &target.server,
+                &cache_file.to_str()
+            );
+        }
+
+        // Try reading from cache first, re-validating expired entries in the
+        // background rather than making this command wait for a probe
+        let cached = read_cache_entry(&cache_file);
+        if let Some(entry) = cached.as_ref() {
+            if let Some(version) = &entry.version {
+                let now = unix_now();
+                if self.is_expired(entry, now) {
+                    self.spawn_revalidation(&cache_file, flags);
+                }
+                // Once the cluster has failed to confirm an expired version,
+                // it is only the last known one and the fallback policy decides
+                if entry.failed_at.is_some() && self.is_past_ttl(entry, now) {
+                    let reason = format!(
+                        "{} failed probe(s) since it reported {}",
+                        entry.failures, version
+                    );
+                    let version = self.fallback_version(target, Some(entry), &reason)?;
+                    return Ok((version, VersionSource::Fallback));
+                }
+                return Ok((version.clone(), VersionSource::Cache));
+            }
+        }
+
+        // Don't hang on a cluster that was unreachable moments ago
+        if let Some(entry) = cached.as_ref().filter(|entry| entry.in_backoff(unix_now())) {
+            let reason = format!("{} failed probe(s), retrying later", entry.failures);
+            let version = self.fallback_version(target, Some(entry), &reason)?;
+            return Ok((version, VersionSource::Fallback));
+        }
+
+        match self.probe_and_cache(target, flags, cached) {
+            (_, Ok(version)) => Ok((version, VersionSource::Probe)),
+            (entry, Err(e)) => {
+                let version = self.fallback_version(target, Some(&entry), &format!("{:#}", e))?;
+                Ok((version, VersionSource::Fallback))
+            }
+        }
+    }
+
+    /// Probes the server and records the outcome in its cache entry, which is
+    /// returned along with the probed version. A failed probe is recorded for
+    /// the backoff.
+    fn probe_and_cache(
+        &self,
+        target: &ClusterTarget,
+        flags: &ConnectionFlags,
+        cached: Option<CacheEntry>,
+    ) -> (CacheEntry, Result<String>) {
+        let cache_file = self.get_version_cache_file(target);
+        let kubeconfig = flags
+            .kubeconfig
+            .as_ref()
+            .map(|path| fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)));
+
+        let probed = self
+            .probe_server_version(target, flags)
+            .and_then(|raw| Ok((normalize_version(&raw)?, raw)));
+        let entry = match &probed {
+            Ok((version, raw)) => CacheEntry {
+                context: target.context.clone(),
+                server: target.server.clone(),
+                kubeconfig,
+                version: Some(version.clone()),
+                server_version: Some(raw.clone()),
+                probed_at: unix_now(),
+                ..CacheEntry::default()
+            },
+            Err(_) => {
+                let mut entry = cached.unwrap_or_else(|| CacheEntry {
+                    context: target.context.clone(),
+                    server: target.server.clone(),
+                    kubeconfig,
+                    ..CacheEntry::default()
+                });
+                entry.failures += 1;
+                entry.failed_at = Some(unix_now());
+                entry
+            }
+        };
+
+        // A cache that cannot be written costs a probe next time, nothing more
+        if let Err(e) = serde_json::to_string_pretty(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(fs::write(&cache_file, json)?))
+        {
+            if self.debug {
+                eprintln!("could not write {}: {:#}", cache_file.display(), e);
+            }
+        }
+        (entry, probed.map(|(version, _)| version))
+    }
+
+    /// Re-probes the cluster selected by `flags`, replacing its cache entry.
+    /// Unlike the shim, a failed probe is an error rather than a fallback.
+    pub fn refresh(&self, flags: &ConnectionFlags) -> Result<CacheEntry> {
+        let target = self.resolve_cluster_target(flags);
+        let cached = read_cache_entry(&self.get_version_cache_file(&target));
+        let (entry, probed) = self.probe_and_cache(&target, flags, cached);
+        probed.map(|_| entry)
+    }
+
+    /// Whether a cached version is older than the TTL at `now`. Entries whose
+    /// last re-validation failed wait for their backoff instead.
+    fn is_expired(&self, entry: &CacheEntry, now: u64) -> bool {
+        self.is_past_ttl(entry, now) && !entry.in_backoff(now)
+    }
+
+    fn is_past_ttl(&self, entry: &CacheEntry, now: u64) -> bool {
+        let ttl = self.cache_ttl.as_secs();
+        ttl > 0 && now >= entry.probed_at.saturating_add(ttl)
+    }
+
+    /// Starts a detached `kubectl-shim` that re-probes the cluster and
+    /// rewrites `cache_file`. A lock file next to the entry keeps concurrent
+    /// commands from starting more than one.
+    fn spawn_revalidation(&self, cache_file: &Path, flags: &ConnectionFlags) {
+        let shim = self.korrect_bin_path.join("kubectl-shim");
+        let lock = cache_file.with_extension("lock");
+        if !shim.exists() || !take_lock(&lock) {
+            return;
+        }
+
+        let mut command = ProcessCommand::new(shim);
+        command
+            .args(flags.to_args())
+            .env(REVALIDATE_ENV, &lock)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        #[cfg(unix)]
+        {
+            // Out of the terminal's process group, so Ctrl-C on the kubectl
+            // command does not take the re-validation down with it
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        match command.spawn() {
+            Ok(_) => {
+                if self.debug {
+                    eprintln!(
+                        "re-validating [{}] in the background.",
+                        cache_file.display()
+                    );
+                }
+            }
+            Err(e) => {
+                fs::remove_file(&lock).ok();
+                if self.debug {
+                    eprintln!("could not start the re-validation: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Why a cache entry no longer belongs to any cluster in the kubeconfig,
+    /// or `None` while it is still in use.
+    pub fn stale_reason(&self, entry: &CacheEntry) -> Option<String> {
+        if let Some(kubeconfig) = &entry.kubeconfig {
+            if !kubeconfig.exists() {
+                return Some(format!("kubeconfig {} is gone", kubeconfig.display()));
+            }
+        }
+        if entry.context.is_empty() {
+            return None;
+        }
+        let paths = kubeconfig_paths(
+            entry.kubeconfig.as_deref().and_then(|path| path.to_str()),
+            env::var_os("KUBECONFIG"),
+            dirs::home_dir(),
+        );
+        match Kubeconfig::load(&paths) {
+            Ok(config) if config.context(&entry.context).is_none() => {
+                Some(format!("context {} is gone", entry.context))
+            }
+            Ok(_) => None,
+            Err(KubeconfigError::NotFound(_)) => Some("the kubeconfig is gone".to_owned()),
+            // Leave entries alone while the kubeconfig is merely unreadable
+            Err(_) => None,
+        }
+    }
+
+    /// Picks a version according to the fallback policy when the server could
+    /// not be probed, telling the user on stderr which one was chosen.
+    fn fallback_version(
+        &self,
+        target: &ClusterTarget,
+        entry: Option<&CacheEntry>,
+        reason: &str,
+    ) -> Result<String> {
+        let server = if target.server.is_empty() {
+            "the cluster"
+        } else {
+            target.server.as_str()
+        };
+        if self.fallback_policy == FallbackPolicy::Fail {
+            return Err(anyhow!(
+                "could not determine the server version of {}: {}",
+                server,
+                reason
+            ));
+        }
+
+        let last_known = entry
+            .and_then(|entry| entry.version.clone())
+            .filter(|_| self.fallback_policy == FallbackPolicy::LastKnown)
+            .map(|version| (version, "last known version"));
+        let newest_installed = || {
+            self.installed_versions()
+                .pop()
+                .filter(|_| self.fallback_policy != FallbackPolicy::Stable)
+                .map(|version| (version, "newest installed version"))
+        };
+        let (version, source) = match last_known.or_else(newest_installed) {
+            Some(found) => found,
+            None => (self.get_current_stable_version()?, "current stable version"),
+        };
+
+        if let Some(known) = entry.and_then(|entry| entry.version.as_deref()) {
+            self.check_skew(&version, known)?;
+        }
+
+        eprintln!(
+            "korrect: could not determine the server version of {} ({}), using the {} {}",
+            server, reason, source, version
+        );
+        Ok(version)
+    }
+
+    /// Picks the kubectl release to run against a server of `server_version`
+    /// according to the selection policy.
+    fn select_version(&self, server_version: &str) -> Result<String> {
+        let server: KubeVersion = server_version.parse()?;
+        let installed: Vec<KubeVersion> = self
+            .installed_versions()
+            .iter()
+            .filter_map(|version| version.parse::<KubeVersion>().ok())
+            .filter(|version| !version.is_prerelease())
+            .collect();
+
+        let selected = match self.selection_policy {
+            SelectionPolicy::Exact => None,
+            // Offline, an installed patch at least as new as the server's is
+            // the latest known one
+            SelectionPolicy::LatestPatchOfMinor => match self.get_latest_patch_version(&server) {
+                Ok(latest) => Some(latest),
+                Err(e) => {
+                    if self.debug {
+                        eprintln!("could not resolve the latest patch release: {:#}", e);
+                    }
+                    installed.into_iter().rev().find(|version| {
+                        version.minor_skew(&server) == Some(0) && *version >= server
+                    })
+                }
+            },
+            // Closest minor version first, newest patch among equals
+            SelectionPolicy::ReuseCompatible => installed
+                .into_iter()
+                .filter(|version| version.within_skew(&server, SUPPORTED_SKEW))
+                .min_by_key(|version| {
+                    (
+                        version.minor_skew(&server).map(i64::unsigned_abs),
+                        std::cmp::Reverse(version.clone()),
+                    )
+                }),
+        };
+
+        Ok(selected.map_or_else(|| server_version.to_owned(), |version| version.tag()))
+    }
+
+    /// Looks up the newest patch release of `server`'s minor version from the
+    /// `stable-X.Y.txt` marker next to `stable.txt`. The marker is kept in the
+    /// cache directory and fetched again once it is older than the cache TTL,
+    /// unless that is zero, falling back to the kept one when the download
+    /// server is unreachable.
+    fn get_latest_patch_version(&self, server: &KubeVersion) -> Result<KubeVersion> {
+        let marker = format!("stable-{}.{}", server.major, server.minor);
+        let marker_file = self
+            .korrect_cache_path
+            .join("releases")
+            .join(format!("{}.txt", marker));
+        let kept = fs::read_to_string(&marker_file)
+            .ok()
+            .and_then(|contents| contents.parse::<KubeVersion>().ok());
+        let fresh = fs::metadata(&marker_file)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| self.cache_ttl.is_zero() || age < self.cache_ttl);
+
+        let latest = match kept {
+            Some(kept) if fresh => kept,
+            kept => match self.store().marker_version(&marker) {
+                Ok(latest) => {
+                    let latest = latest.release();
+                    if let Some(dir) = marker_file.parent() {
+                        fs::create_dir_all(dir).ok();
+                    }
+                    fs::write(&marker_file, latest.tag()).ok();
+                    latest
+                }
+                Err(e) => kept.ok_or(e)?,
+            },
+        };
+        if latest.minor_skew(server) != Some(0) || latest < *server {
+            return Err(anyhow!(
+                "{} names {}, expected {} or newer",
+                marker,
+                latest,
+                server
+            ));
+        }
+        Ok(latest)
+    }
+
+    /// In strict mode, refuses a kubectl outside the supported skew of the server.
+    fn check_skew(&self, kubectl_version: &str, server_version: &str) -> Result<()> {
+        if !self.strict_skew {
+            return Ok(());
+        }
+        let kubectl: KubeVersion = kubectl_version.parse()?;
+        let server: KubeVersion = server_version.parse()?;
+        if !kubectl.within_skew(&server, SUPPORTED_SKEW) {
+            return Err(anyhow!(
+                "kubectl {} is outside the supported version skew of server {} (strict mode)",
+                kubectl_version,
+                server_version
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resolves the cluster selected by `flags` (or the current context). A
+    /// kubeconfig that cannot be resolved maps to the default target, which
+    /// keeps a single cache entry for it unless `--server` names the cluster.
+    pub fn resolve_cluster_target(&self, flags: &ConnectionFlags) -> ClusterTarget {
+        let paths = kubeconfig_paths(
+            flags.kubeconfig.as_deref(),
+            env::var_os("KUBECONFIG"),
+            dirs::home_dir(),
+        );
+        let resolved = Kubeconfig::load(&paths)
+            .map_err(anyhow::Error::from)
+            .and_then(|config| ClusterTarget::from_kubeconfig(&config, flags));
+        match resolved {
+            Ok(target) => target,
+            Err(e) => {
+                if self.debug {
+                    eprintln!("could not resolve cluster from {:?}: {}", paths, e);
+                }
+                ClusterTarget {
+                    context: flags.context.clone().unwrap_or_default(),
+                    server: flags.server.clone().unwrap_or_default(),
+                    ..ClusterTarget::default()
+                }
+            }
+        }
+    }
+
+    /// Asks the API server for its version directly, only spawning kubectl for
+    /// clusters korrect cannot resolve or authenticate against by itself.
+    fn probe_server_version(
+        &self,
+        target: &ClusterTarget,
+        flags: &ConnectionFlags,
+    ) -> Result<String> {
+        if target.cluster.server.is_none() {
+            return self.probe_with_kubectl(flags);
+        }
+        match probe::probe_server_version(&target.cluster, target.user.as_ref(), self.probe_timeout)
+        {
+            Ok(version) => Ok(version),
+            Err(e) if e.needs_kubectl() => {
+                if self.debug {
+                    eprintln!(
+                        "falling back to kubectl to probe [{}]: {}",
+                        target.server, e
+                    );
+                }
+                self.probe_with_kubectl(flags)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Asks kubectl for the server version, killing it if it outlives the
+    /// probe timeout, e.g. on an auth plugin waiting for a login.
+    fn probe_with_kubectl(&self, flags: &ConnectionFlags) -> Result<String> {
+        let local_kubectl = self.get_probe_kubectl()?;
+
+        let mut child = ProcessCommand::new(local_kubectl)
+            .args(flags.to_args())
+            .arg(format!(
+                "--request-timeout={}s",
+                self.probe_timeout.as_secs()
+            ))
+            .arg("version")
+            .arg("-o")
+            .arg("json")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdout = child.stdout.take().ok_or_else(|| anyhow!("no stdout"))?;
+        let reader = thread::spawn(move || {
+            let mut buffer = Vec::new();
+            stdout.read_to_end(&mut buffer).map(|_| buffer)
+        });
+        if wait_timeout(&mut child, self.probe_timeout)?.is_none() {
+            return Err(anyhow!(
+                "kubectl did not report the server version within {}s",
+                self.probe_timeout.as_secs()
+            ));
+        }
+        let output = reader
+            .join()
+            .map_err(|_| anyhow!("failed to read kubectl output"))??;
+
+        let json: Value = serde_json::from_slice(&output)?;
+        json["serverVersion"]["gitVersion"]
+            .as_str()
+            .map(str::to_owned)
+            .ok_or_else(|| anyhow!("kubectl could not determine the server version"))
+    }
+
+    /// Returns a kubectl able to query the server version, preferring the newest
+    /// installed binary and only downloading the current stable release when
+    /// nothing is installed yet.
+    fn get_probe_kubectl(&self) -> Result<PathBuf> {
+        let store = self.store();
+        if let Some(version) = store.installed_versions().last() {
+            return Ok(store.kubectl_path(version));
+        }
+        let current_stable_version = self.get_current_stable_version()?;
+        self.download_kubectl(&current_stable_version)
+    }
+
+    /// Lists the kubectl versions installed in the bin directory, oldest first.
+    pub fn installed_versions(&self) -> Vec<String> {
+        self.store().installed_versions()
+    }
+
+    pub fn get_version_cache_file(&self, target: &ClusterTarget) -> PathBuf {
+        self.korrect_cache_path
+            .join(format!("{}.json", target.cache_key()))
+    }
+
+    pub fn download_kubectl(&self, version: &str) -> Result<PathBuf> {
+        self.store().install(version)
+    }
+
+    /// Decides which kubectl to run for the cluster selected by `flags`
+    /// without downloading it. When the server version is cached this touches
+    /// neither the network nor the cluster.
+    ///
+    /// The first of these decides: `korrect exec --version`, the program name,
+    /// `KORRECT_KUBECTL_VERSION`, a project version file, the context's pin,
+    /// and finally the cluster.
+    pub fn resolve(&self, flags: &ConnectionFlags) -> Result<Resolution> {
+        let target = self.resolve_cluster_target(flags);
+        let requested = [
+            (&self.flag_version, VersionSource::Flag),
+            (&self.program_version, VersionSource::ProgramName),
+            (&self.env_version, VersionSource::Env),
+        ];
+        for (spec, source) in requested {
+            if let Some(spec) = spec {
+                let version = self.resolve_requested(spec).with_context(|| {
+                    format!("invalid kubectl version {} from the {}", spec, source)
+                })?;
+                return Ok(self.override_resolution(target, version, source));
+            }
+        }
+        if let Some(file) = self.project_dir.as_deref().and_then(find_version_file) {
+            let version = self
+                .resolve_requested(&file.spec)
+                .with_context(|| format!("invalid kubectl version in {}", file.path.display()))?;
+            let mut resolution =
+                self.override_resolution(target, version, VersionSource::ProjectFile);
+            resolution.version_file = Some(file);
+            return Ok(resolution);
+        }
+        if let Some(pin) = self.pin(&target.context) {
+            let mut resolution =
+                self.override_resolution(target, pin.version.clone(), VersionSource::Pin);
+            resolution.pin = Some(pin);
+            return Ok(resolution);
+        }
+
+        let (server_version, source) = self.get_server_version(&target, flags)?;
+        let version = self.select_version(&server_version)?;
+        self.check_skew(&version, &server_version)?;
+
+        let store = self.store();
+        let path = store.kubectl_path(&version);
+        let cache_file = self.get_version_cache_file(&target);
+        Ok(Resolution {
+            installed: store.is_installed(&version),
+            path,
+            version,
+            source,
+            server_version: (source != VersionSource::Fallback).then_some(server_version),
+            context: target.context,
+            server: target.server,
+            cache_entry: read_cache_entry(&cache_file),
+            cache_file,
+            pin: None,
+            version_file: None,
+        })
+    }
+
+    /// The version pinned to `context`. An unreadable pins file is treated as
+    /// having no pins so the shim keeps working.
+    pub fn pin(&self, context: &str) -> Option<Pin> {
+        if context.is_empty() {
+            return None;
+        }
+        match read_pins(&self.korrect_config_path) {
+            Ok(mut pins) => pins.remove(context),
+            Err(e) => {
+                if self.debug {
+                    eprintln!("ignoring pins: {:#}", e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Turns a requested version spec into a release, preferring an
+    /// installed kubectl so that `1.27` does not cost a lookup every time.
+    fn resolve_requested(&self, spec: &str) -> Result<String> {
+        let store = self.store();
+        match store.installed_match(spec) {
+            Some(version) => Ok(version),
+            None => store.resolve_spec(spec, false),
+        }
+    }
+
+    /// Resolves to an overriding version as is: no probe, no selection policy
+    /// and no skew check, as an override is a deliberate choice.
+    fn override_resolution(
+        &self,
+        target: ClusterTarget,
+        version: String,
+        source: VersionSource,
+    ) -> Resolution {
+        let store = self.store();
+        let path = store.kubectl_path(&version);
+        let cache_file = self.get_version_cache_file(&target);
+        let cache_entry = read_cache_entry(&cache_file);
+        Resolution {
+            installed: store.is_installed(&version),
+            path,
+            version,
+            source,
+            server_version: cache_entry.as_ref().and_then(|entry| entry.version.clone()),
+            context: target.context,
+            server: target.server,
+            cache_entry,
+            cache_file,
+            pin: None,
+            version_file: None,
+        }
+    }
+
+    /// Resolves the kubectl binary to run for the cluster selected by `flags`,
+    /// downloading it if needed. When the server version is cached and that
+    /// kubectl is installed this touches neither the network nor the cluster.
+    pub fn resolve_kubectl(&self, flags: &ConnectionFlags) -> Result<Resolution> {
+        let mut resolution = self.resolve(flags)?;
+        self.install_resolution(&mut resolution)?;
+        Ok(resolution)
+    }
+
+    /// Downloads the kubectl of a resolution made by `resolve` unless it is
+    /// installed, and records that it is being used.
+    pub fn install_resolution(&self, resolution: &mut Resolution) -> Result<()> {
+        if !resolution.installed && !self.auto_download {
+            return Err(anyhow!(
+                "kubectl {} is not installed and auto-download is off, install it with `korrect install {}`",
+                resolution.version,
+                resolution.version
+            ));
+        }
+
+        // Download target version
+        resolution.path = self.download_kubectl(&resolution.version)?;
+        resolution.installed = true;
+
+        self.record_selection(resolution);
+        self.store().record_use(&resolution.version);
+        Ok(())
+    }
+
+    /// The context names in the kubeconfig `flags` select, in file order.
+    pub fn context_names(&self, flags: &ConnectionFlags) -> Result<Vec<String>> {
+        let paths = kubeconfig_paths(
+            flags.kubeconfig.as_deref(),
+            env::var_os("KUBECONFIG"),
+            dirs::home_dir(),
+        );
+        let config = Kubeconfig::load(&paths)?;
+        Ok(config
+            .contexts
+            .into_iter()
+            .map(|context| context.name)
+            .collect())
+    }
+
+    /// Resolves kubectl for `args`, runs it and returns its exit code. With
+    /// skew watching on, a skew warning on kubectl's stderr invalidates the
+    /// cluster's cache entry, and read-only commands are re-run right away with
+    /// a freshly resolved kubectl when re-running is enabled as well.
+    pub fn run_kubectl(&self, args: &[String]) -> Result<i32> {
+        let flags = ConnectionFlags::parse(args);
+        let resolution = self.resolve_kubectl(&flags)?;
+        self.log_resolution(&resolution);
+
+        if !self.watch_skew {
+            let status = run_forwarding_signals(kubectl_command(&resolution.path).args(args))?;
+            return Ok(exit_code(status));
+        }
+
+        // Hold back the output of commands that may be re-run so it is not
+        // printed twice. An overriding version was chosen on purpose, so its
+        // skew says nothing about a stale cache entry.
+        let invalidate = !resolution.source.is_override();
+        let rerun = invalidate && self.rerun_on_skew && is_read_only(args);
+        let (status, skew, stdout) = run_watching_skew(&resolution.path, args, rerun)?;
+        if let Some(warning) = skew.filter(|_| invalidate) {
+            fs::remove_file(&resolution.cache_file).ok();
+            eprintln!(
+                "korrect: kubectl {} is outside the supported skew of {} ({}), the server version will be probed again",
+                resolution.version,
+                if resolution.context.is_empty() {
+                    &resolution.server
+                } else {
+                    &resolution.context
+                },
+                warning
+            );
+            if rerun {
+                let fresh = self.resolve_kubectl(&flags)?;
+                if fresh.version != resolution.version {
+                    eprintln!("korrect: re-running with kubectl {}", fresh.version);
+                    let status = run_forwarding_signals(kubectl_command(&fresh.path).args(args))?;
+                    return Ok(exit_code(status));
+                }
+            }
+        }
+        io::stdout().write_all(&stdout)?;
+        Ok(exit_code(status))
+    }
+
+    /// Runs kubectl for `args` like `run_kubectl`, but on Unix replaces this
+    /// process with it instead of waiting on a child, unless skew watching
+    /// needs korrect to stay around. Only returns when kubectl could not be
+    /// started or ran as a child.
+    pub fn exec_kubectl(&self, args: &[String]) -> Result<i32> {
+        if self.watch_skew || cfg!(not(unix)) {
+            return self.run_kubectl(args);
+        }
+        let resolution = self.resolve_kubectl(&ConnectionFlags::parse(args))?;
+        self.log_resolution(&resolution);
+
+        #[cfg(unix)]
+        {
+            let error = crate::process::exec_kubectl(&resolution.path, args);
+            Err(anyhow!(
+                "failed to run {}: {}",
+                resolution.path.display(),
+                error
+            ))
+        }
+        #[cfg(not(unix))]
+        unreachable!()
+    }
+
+    fn log_resolution(&self, resolution: &Resolution) {
+        if self.debug {
+            eprintln!(
+                "using [{}] from the {}.",
+                resolution.version, resolution.source
+            );
+        }
+    }
+
+    /// Notes in the cluster's cache entry which kubectl the selection policy
+    /// picked for its server version, so `korrect list` can map it. The entry
+    /// is only rewritten when the choice changes.
+    fn record_selection(&self, resolution: &Resolution) {
+        if resolution.source.is_override() {
+            return;
+        }
+        let (Some(server_version), Some(mut entry)) = (
+            resolution.server_version.as_deref(),
+            resolution.cache_entry.clone(),
+        ) else {
+            return;
+        };
+        let selected = resolution.version.as_str();
+        if entry.version.as_deref() != Some(server_version)
+            || entry.kubectl_version() == Some(selected)
+        {
+            return;
+        }
+        entry.kubectl = (selected != server_version).then(|| selected.to_owned());
+        if let Ok(json) = serde_json::to_string_pretty(&entry) {
+            fs::write(&resolution.cache_file, json).ok();
+        }
+    }
+}
+
+/// The version spec forced by the name the shim is invoked by: a symlink
+/// named `kubectl-1.27` or `kubectl@v1.27.3` runs that version.
+pub fn program_version(argv0: &str) -> Option<String> {
+    let name = Path::new(argv0).file_name()?.to_str()?;
+    let spec = name
+        .strip_prefix("kubectl@")
+        .or_else(|| name.strip_prefix("kubectl-"))?;
+    let digits = spec.strip_prefix('v').unwrap_or(spec);
+    digits
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then(|| spec.to_owned())
+}
+
+/// Set for a background `kubectl-shim` that only re-validates a cache entry,
+/// holding the path of the lock file to release when done.
+pub const REVALIDATE_ENV: &str = "KORRECT_REVALIDATE";
+
+/// How long a re-validation lock is honoured before it is assumed abandoned.
+const REVALIDATE_LOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Creates `lock` unless another process holds it, breaking locks older than
+/// `REVALIDATE_LOCK_TIMEOUT`.
+fn take_lock(lock: &Path) -> bool {
+    let create = || {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(lock)
+            .is_ok()
+    };
+    if create() {
+        return true;
+    }
+    let abandoned = fs::metadata(lock)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > REVALIDATE_LOCK_TIMEOUT);
+    abandoned && fs::remove_file(lock).is_ok() && create()
+}
+
+/// Runs kubectl, copying its stderr through while looking for the version
+/// skew warning, and capturing its stdout when `capture_stdout` is set.
+fn run_watching_skew(
+    path: &Path,
+    args: &[String],
+    capture_stdout: bool,
+) -> Result<(ExitStatus, Option<String>, Vec<u8>)> {
+    let mut child = kubectl_command(path)
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stdout(if capture_stdout {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let _forwarding = ForwardSignals::to(&child);
+
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("kubectl stderr was not captured"))?;
+    let watcher = thread::spawn(move || -> io::Result<Option<String>> {
+        // Forward every chunk as it arrives so that prompts show up, and only
+        // split lines for the warning check
+        let mut buffer = [0; 8192];
+        let mut line = Vec::new();
+        let mut warning = None;
+        loop {
+            let read = stderr.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            io::stderr().write_all(&buffer[..read])?;
+            line.extend_from_slice(&buffer[..read]);
+            while let Some(end) = line.iter().position(|&b| b == b'\n') {
+                let text: Vec<u8> = line.drain(..=end).collect();
+                warning = warning.or_else(|| parse_skew_warning(&String::from_utf8_lossy(&text)));
+            }
+        }
+        Ok(warning.or_else(|| parse_skew_warning(&String::from_utf8_lossy(&line))))
+    });
+
+    let mut stdout = Vec::new();
+    if let Some(mut out) = child.stdout.take() {
+        out.read_to_end(&mut stdout)?;
+    }
+    let status = child.wait()?;
+    let warning = watcher
+        .join()
+        .map_err(|_| anyhow!("the kubectl stderr reader panicked"))??;
+    Ok((status, warning, stdout))
+}
+
+/// Recognises kubectl's warning that it is too far from the server, returning
+/// the `client X.Y, server X.Y` pair it reports.
+fn parse_skew_warning(line: &str) -> Option<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"version difference between client \((\d+\.\d+)\) and server \((\d+\.\d+)\) exceeds the supported minor version skew",
+        )
+        .expect("the skew warning pattern is valid")
+    });
+    let captures = pattern.captures(line)?;
+    Some(format!("client {}, server {}", &captures[1], &captures[2]))
+}
+
+/// kubectl global flags that take a separate value, so that the value is not
+/// mistaken for the command.
+const VALUE_FLAGS: &[&str] = &[
+    "--kubeconfig",
+    "--context",
+    "--cluster",
+    "--server",
+    "-s",
+    "--user",
+    "--namespace",
+    "-n",
+    "--token",
+    "--as",
+    "--as-group",
+    "--as-uid",
+    "--request-timeout",
+    "--certificate-authority",
+    "--client-certificate",
+    "--client-key",
+    "--tls-server-name",
+    "--cache-dir",
+    "-v",
+    "--v",
+    "--log-file",
+    "--profile",
+    "--profile-output",
+];
+
+/// Whether kubectl `args` run a command that only reads and returns, so that
+/// running it twice is harmless. Watches and followed logs never return.
+fn is_read_only(args: &[String]) -> bool {
+    const READ_ONLY: &[&str] = &[
+        "get",
+        "describe",
+        "explain",
+        "logs",
+        "top",
+        "version",
+        "api-resources",
+        "api-versions",
+        "cluster-info",
+    ];
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    let mut verb = None;
+    while let Some(arg) = args.next() {
+        if arg.starts_with('-') {
+            if VALUE_FLAGS.contains(&arg.as_str()) {
+                args.next();
+            }
+            continue;
+        }
+        verb = Some(arg.as_str());
+        break;
+    }
+    let Some(verb) = verb.filter(|verb| READ_ONLY.contains(verb)) else {
+        return false;
+    };
+    let streaming: &[&str] = match verb {
+        "get" => &["-w", "--watch", "--watch-only"],
+        "logs" => &["-f", "--follow"],
+        _ => &[],
+    };
+    !args.any(|arg| streaming.contains(&arg.split('=').next().unwrap_or_default()))
+}
+
+/// Maps the version reported by a server, e.g. `v1.29.3-eks-adc7111`, to the
+/// upstream kubectl release to run for it, e.g. `v1.29.3`.
+fn normalize_version(version: &str) -> Result<String> {
+    version
+        .parse::<KubeVersion>()
+        .ok()
+        .or_else(|| KubeVersion::find(version))
+        .map(|version| version.release().tag())
+        .ok_or_else(|| anyhow!("Version string does not match the expected pattern"))
+}
+
+#[cfg(test)]
+mod shim_tests {
+    use super::*;
+    use crate::download::DownloadError;
+    use regex::Regex;
+    use reqwest::StatusCode;
+    use std::env;
+    use std::fs;
+
+    use crate::test_support::{remove_temp_home, setup_temp_home, test_config};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_normalize_version() {
+        assert_eq!(normalize_version("v1.2.3").unwrap(), "v1.2.3");
+        assert_eq!(normalize_version("v7.24.31").unwrap(), "v7.24.31");
+        assert_eq!(
+            normalize_version("somethingv1.2.3-alpha").unwrap(),
+            "v1.2.3"
+        );
+        assert_eq!(normalize_version("v1.2.3-alpha").unwrap(), "v1.2.3");
+        assert_eq!(normalize_version("1.29.3").unwrap(), "v1.29.3");
+        assert_eq!(normalize_version("v1.29.3-eks-adc7111").unwrap(), "v1.29.3");
+        assert_eq!(normalize_version("v1.28.9-gke.1000000").unwrap(), "v1.28.9");
+        assert_eq!(normalize_version("v1.30.2+k3s1").unwrap(), "v1.30.2");
+        assert_eq!(normalize_version("v1.27.16+rke2r1").unwrap(), "v1.27.16");
+
+        // Cases that should fail to match and return an error
+        assert!(normalize_version("version1.2.3").is_err());
+        assert!(normalize_version("invalid").is_err());
+    }
+
+    const KUBECONFIG: &str = r#"
+apiVersion: v1
+kind: Config
+current-context: dev
+clusters:
+- name: dev-cluster
+  cluster:
+    server: https://dev.example.com:6443
+    certificate-authority-data: ZGV2LWNh
+- name: prod-cluster
+  cluster:
+    server: https://prod.example.com:6443
+    certificate-authority: /etc/prod-ca.crt
+contexts:
+- name: dev
+  context:
+    cluster: dev-cluster
+    user: dev-user
+- name: dev-admin
+  context:
+    cluster: dev-cluster
+    user: admin
+- name: prod
+  context:
+    cluster: prod-cluster
+    user: prod-user
+users:
+- name: dev-user
+  user:
+    token: abc
+"#;
+
+    fn parse(kubeconfig: &str) -> Kubeconfig {
+        Kubeconfig::parse(kubeconfig, None).unwrap()
+    }
+
+    fn context_flags(context: &str) -> ConnectionFlags {
+        ConnectionFlags {
+            context: Some(context.to_owned()),
+            ..ConnectionFlags::default()
+        }
+    }
+
+    fn kubeconfig_flags(kubeconfig: &str) -> ConnectionFlags {
+        ConnectionFlags {
+            kubeconfig: Some(kubeconfig.to_owned()),
+            ..ConnectionFlags::default()
+        }
+    }
+
+    fn server_version(config: &KorrectShimConfig, flags: &ConnectionFlags) -> Result<String> {
+        config
+            .get_server_version(&config.resolve_cluster_target(flags), flags)
+            .map(|(version, _)| version)
+    }
+
+    fn write_cache_entry(config: &KorrectShimConfig, target: &ClusterTarget, version: &str) {
+        let entry = CacheEntry {
+            context: target.context.clone(),
+            server: target.server.clone(),
+            version: Some(version.to_owned()),
+            probed_at: unix_now(),
+            ..CacheEntry::default()
+        };
+        fs::write(
+            config.get_version_cache_file(target),
+            serde_json::to_string(&entry).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_get_version_cache_file() {
+        let (temp_dir, _) = setup_temp_home();
+        let config = test_config();
+
+        let dev = ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &ConnectionFlags::default())
+            .unwrap();
+        let cache_file = config.get_version_cache_file(&dev);
+        assert!(cache_file.starts_with(&config.korrect_cache_path));
+        assert_eq!(
+            cache_file.file_name().unwrap().to_str().unwrap(),
+            format!("{}.json", dev.cache_key())
+        );
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_cluster_target_from_kubeconfig() {
+        let dev = ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &ConnectionFlags::default())
+            .unwrap();
+        assert_eq!(dev.context, "dev");
+        assert_eq!(dev.server, "https://dev.example.com:6443");
+
+        let prod =
+            ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &context_flags("prod")).unwrap();
+        assert_eq!(prod.server, "https://prod.example.com:6443");
+        assert_eq!(prod.ca_identity, "file:/etc/prod-ca.crt");
+
+        assert!(
+            ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &context_flags("missing")).is_err()
+        );
+        assert!(ClusterTarget::from_kubeconfig(
+            &parse("apiVersion: v1"),
+            &ConnectionFlags::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_connection_flags_parse() {
+        let flags = ConnectionFlags::parse(&[
+            "--context",
+            "prod",
+            "get",
+            "--kubeconfig=/tmp/config",
+            "pods",
+            "-n",
+            "default",
+        ]);
+        assert_eq!(
+            flags,
+            ConnectionFlags {
+                kubeconfig: Some("/tmp/config".to_owned()),
+                context: Some("prod".to_owned()),
+                ..ConnectionFlags::default()
+            }
+        );
+
+        let flags = ConnectionFlags::parse(&["--cluster=edge", "-s", "https://10.0.0.1", "get"]);
+        assert_eq!(flags.cluster.as_deref(), Some("edge"));
+        assert_eq!(flags.server.as_deref(), Some("https://10.0.0.1"));
+        assert_eq!(
+            ConnectionFlags::parse(&["-shttps://10.0.0.2"])
+                .server
+                .as_deref(),
+            Some("https://10.0.0.2")
+        );
+
+        // Flags after `--` belong to the remote command
+        let flags = ConnectionFlags::parse(&["exec", "pod", "--", "tool", "--context", "other"]);
+        assert_eq!(flags, ConnectionFlags::default());
+    }
+
+    #[test]
+    fn test_cluster_target_honors_flags() {
+        let flags = ConnectionFlags {
+            cluster: Some("prod-cluster".to_owned()),
+            ..ConnectionFlags::default()
+        };
+        let target = ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &flags).unwrap();
+        assert_eq!(target.context, "dev");
+        assert_eq!(target.server, "https://prod.example.com:6443");
+
+        let flags = ConnectionFlags {
+            server: Some("https://override.example.com".to_owned()),
+            ..ConnectionFlags::default()
+        };
+        let target = ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &flags).unwrap();
+        assert_eq!(target.server, "https://override.example.com");
+    }
+
+    #[test]
+    fn test_get_server_version_per_context() {
+        let (temp_dir, _) = setup_temp_home();
+        let config = test_config();
+
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(&kubeconfig, KUBECONFIG).unwrap();
+        let kubeconfig = kubeconfig.to_str().unwrap();
+
+        let dev = config.resolve_cluster_target(&kubeconfig_flags(kubeconfig));
+        write_cache_entry(&config, &dev, "v1.29.0");
+        let prod = config.resolve_cluster_target(&ConnectionFlags {
+            context: Some("prod".to_owned()),
+            ..kubeconfig_flags(kubeconfig)
+        });
+        write_cache_entry(&config, &prod, "v1.31.2");
+
+        let args = ["--kubeconfig", kubeconfig, "--context=prod", "get", "nodes"];
+        let version = server_version(&config, &ConnectionFlags::parse(&args)).unwrap();
+        assert_eq!(version, "v1.31.2");
+
+        let version = server_version(&config, &kubeconfig_flags(kubeconfig)).unwrap();
+        assert_eq!(version, "v1.29.0");
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_get_server_version_probes_natively() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        // A native probe needs neither a kubectl nor the download server
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+
+        let mut server = mockito::Server::new();
+        let version_mock = server
+            .mock("GET", "/version")
+            .match_header("authorization", "Bearer abc")
+            .with_status(200)
+            .with_body(r#"{"major":"1","minor":"30","gitVersion":"v1.30.5-gke.1014001"}"#)
+            .expect(1)
+            .create();
+
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(
+            &kubeconfig,
+            KUBECONFIG.replace("https://dev.example.com:6443", &server.url()),
+        )
+        .unwrap();
+        let flags = kubeconfig_flags(kubeconfig.to_str().unwrap());
+
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.30.5");
+        // The second lookup is answered by the cache
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.30.5");
+        version_mock.assert();
+
+        let target = config.resolve_cluster_target(&flags);
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.context, "dev");
+        assert_eq!(entry.server, server.url());
+        assert_eq!(entry.server_version.as_deref(), Some("v1.30.5-gke.1014001"));
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_refresh_replaces_cached_version() {
+        let (temp_dir, _) = setup_temp_home();
+        let config = test_config();
+
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/version")
+            .with_status(200)
+            .with_body(r#"{"gitVersion":"v1.31.2"}"#)
+            .create();
+        let flags = unreachable_kubeconfig(&temp_dir, &server.url());
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.30.1");
+
+        let entry = config.refresh(&flags).unwrap();
+        assert_eq!(entry.version.as_deref(), Some("v1.31.2"));
+        assert_eq!(
+            entry.kubeconfig,
+            Some(fs::canonicalize(temp_dir.path().join("kubeconfig")).unwrap())
+        );
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.31.2");
+
+        // A failed refresh is an error and keeps the last known version
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.30.1");
+        assert!(config.refresh(&flags).is_err());
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.version.as_deref(), Some("v1.30.1"));
+        assert_eq!(entry.failures, 1);
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_stale_reason() {
+        let (temp_dir, _) = setup_temp_home();
+        let config = test_config();
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(&kubeconfig, KUBECONFIG).unwrap();
+
+        let entry = |context: &str, kubeconfig: PathBuf| CacheEntry {
+            context: context.to_owned(),
+            kubeconfig: Some(kubeconfig),
+            ..CacheEntry::default()
+        };
+        assert_eq!(
+            config.stale_reason(&entry("prod", kubeconfig.clone())),
+            None
+        );
+        assert_eq!(
+            config.stale_reason(&entry("staging", kubeconfig.clone())),
+            Some("context staging is gone".to_owned())
+        );
+        let missing = temp_dir.path().join("missing");
+        assert!(config
+            .stale_reason(&entry("prod", missing))
+            .is_some_and(|reason| reason.contains("is gone")));
+
+        remove_temp_home(temp_dir);
+    }
+
+    fn unreachable_kubeconfig(temp_dir: &TempDir, server: &str) -> ConnectionFlags {
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(
+            &kubeconfig,
+            KUBECONFIG.replace("https://dev.example.com:6443", server),
+        )
+        .unwrap();
+        kubeconfig_flags(kubeconfig.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_kubectl_probe_is_killed_at_timeout() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.fallback_policy = FallbackPolicy::Fail;
+        config.probe_timeout = Duration::from_millis(200);
+        script_kubectl(&config, "v1.30.1", "exec sleep 10");
+
+        // Without a server to query directly, the probe falls back to kubectl
+        let missing = temp_dir.path().join("missing");
+        let flags = kubeconfig_flags(missing.to_str().unwrap());
+        let started = std::time::Instant::now();
+        let err = server_version(&config, &flags).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(format!("{:#}", err).contains("did not report"), "{:#}", err);
+
+        let target = config.resolve_cluster_target(&flags);
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.failures, 1);
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_unreachable_cluster_fails_with_fail_policy() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.fallback_policy = FallbackPolicy::Fail;
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let err = server_version(&config, &flags).unwrap_err();
+        assert!(err.to_string().contains("http://127.0.0.1:9"), "{}", err);
+
+        let target = config.resolve_cluster_target(&flags);
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.version, None);
+        assert_eq!(entry.failures, 1);
+        assert!(entry.in_backoff(unix_now()));
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_unreachable_cluster_is_not_reprobed_during_backoff() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.fallback_policy = FallbackPolicy::NewestInstalled;
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        fs::write(config.korrect_bin_path.join("kubectl-v1.28.3"), "").unwrap();
+
+        let mut server = mockito::Server::new();
+        let unavailable = server
+            .mock("GET", "/version")
+            .with_status(503)
+            .expect(1)
+            .create();
+
+        let flags = unreachable_kubeconfig(&temp_dir, &server.url());
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.28.3");
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.28.3");
+        unavailable.assert();
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_fallback_policy_from_str() {
+        assert_eq!(
+            "last-known".parse::<FallbackPolicy>().unwrap(),
+            FallbackPolicy::LastKnown
+        );
+        assert_eq!(
+            "newest-installed".parse::<FallbackPolicy>().unwrap(),
+            FallbackPolicy::NewestInstalled
+        );
+        assert_eq!(
+            "fail".parse::<FallbackPolicy>().unwrap(),
+            FallbackPolicy::Fail
+        );
+        assert!("sometimes".parse::<FallbackPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_selection_policy_from_str() {
+        assert_eq!(
+            "exact".parse::<SelectionPolicy>().unwrap(),
+            SelectionPolicy::Exact
+        );
+        assert_eq!(
+            "latest-patch-of-minor".parse::<SelectionPolicy>().unwrap(),
+            SelectionPolicy::LatestPatchOfMinor
+        );
+        assert_eq!(
+            "reuse-compatible".parse::<SelectionPolicy>().unwrap(),
+            SelectionPolicy::ReuseCompatible
+        );
+        assert!("closest".parse::<SelectionPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_select_version_reuse_compatible() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.selection_policy = SelectionPolicy::ReuseCompatible;
+
+        for name in [
+            "kubectl-v1.28.3",
+            "kubectl-v1.29.1",
+            "kubectl-v1.29.5",
+            "kubectl-v1.31.0",
+            "kubectl-v1.31.1-rc.0",
+        ] {
+            fs::write(config.korrect_bin_path.join(name), "").unwrap();
+        }
+
+        // Same minor first, newest patch among them
+        assert_eq!(config.select_version("v1.29.9").unwrap(), "v1.29.5");
+        // Equally close minors, the newest wins
+        assert_eq!(config.select_version("v1.30.2").unwrap(), "v1.31.0");
+        // Nothing within the skew window
+        assert_eq!(config.select_version("v1.33.0").unwrap(), "v1.33.0");
+
+        config.selection_policy = SelectionPolicy::Exact;
+        assert_eq!(config.select_version("v1.29.9").unwrap(), "v1.29.9");
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_select_version_latest_patch_of_minor() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut server = mockito::Server::new();
+        let mut config = test_config();
+        config.dl_url = server.url();
+        config.selection_policy = SelectionPolicy::LatestPatchOfMinor;
+
+        let mock = server
+            .mock("GET", "/release/stable-1.29.txt")
+            .with_status(200)
+            .with_body("v1.29.14")
+            .expect(1)
+            .create();
+
+        assert_eq!(config.select_version("v1.29.3").unwrap(), "v1.29.14");
+
+        // The marker is kept for the cache TTL instead of asked for again
+        assert_eq!(config.select_version("v1.29.1").unwrap(), "v1.29.14");
+        mock.assert();
+
+        // and fetched again once it is older, picking up a newer patch even
+        // though an older one is installed
+        fs::write(config.korrect_bin_path.join("kubectl-v1.29.14"), "").unwrap();
+        let marker = config
+            .korrect_cache_path
+            .join("releases")
+            .join("stable-1.29.txt");
+        fs::File::options()
+            .write(true)
+            .open(&marker)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - config.cache_ttl * 2)
+            .unwrap();
+        let mock = server
+            .mock("GET", "/release/stable-1.29.txt")
+            .with_status(200)
+            .with_body("v1.29.15")
+            .expect(1)
+            .create();
+        assert_eq!(config.select_version("v1.29.3").unwrap(), "v1.29.15");
+        mock.assert();
+
+        // Offline, the kept marker is used
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        assert_eq!(config.select_version("v1.29.3").unwrap(), "v1.29.15");
+
+        // and without one, an installed patch at least as new as the server
+        fs::write(config.korrect_bin_path.join("kubectl-v1.28.9"), "").unwrap();
+        assert_eq!(config.select_version("v1.28.2").unwrap(), "v1.28.9");
+
+        // Falls back to the exact version when the marker is unavailable
+        assert_eq!(config.select_version("v1.30.2").unwrap(), "v1.30.2");
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_strict_skew() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+
+        assert!(config.check_skew("v1.31.0", "v1.29.1").is_ok());
+
+        config.strict_skew = true;
+        assert!(config.check_skew("v1.30.0", "v1.29.1").is_ok());
+        assert!(config.check_skew("v1.28.9", "v1.29.1").is_ok());
+        let err = config.check_skew("v1.31.0", "v1.29.1").unwrap_err();
+        assert!(err.to_string().contains("skew"), "{}", err);
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_unconfirmed_cache_entry_uses_fallback_policy() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        config.auto_download = false;
+
+        // Cached two TTLs ago, and the re-validation since then failed
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        let now = unix_now();
+        let entry = CacheEntry {
+            context: target.context.clone(),
+            server: target.server.clone(),
+            version: Some("v1.29.1".to_owned()),
+            probed_at: now - 2 * config.cache_ttl.as_secs(),
+            failures: 1,
+            failed_at: Some(now),
+            ..CacheEntry::default()
+        };
+        let cache_file = config.get_version_cache_file(&target);
+        fs::write(&cache_file, serde_json::to_string(&entry).unwrap()).unwrap();
+        fs::write(config.korrect_bin_path.join("kubectl-v1.29.1"), "").unwrap();
+        fs::write(config.korrect_bin_path.join("kubectl-v1.31.0"), "").unwrap();
+
+        let (version, source) = config.get_server_version(&target, &flags).unwrap();
+        assert_eq!(version, "v1.29.1");
+        assert_eq!(source, VersionSource::Fallback);
+
+        config.fallback_policy = FallbackPolicy::NewestInstalled;
+        let resolution = config.resolve(&flags).unwrap();
+        assert_eq!(resolution.version, "v1.31.0");
+        assert_eq!(resolution.source, VersionSource::Fallback);
+
+        // The fallback is held to the last version the cluster reported
+        config.strict_skew = true;
+        let err = config.resolve(&flags).unwrap_err();
+        assert!(err.to_string().contains("skew"), "{}", err);
+
+        config.fallback_policy = FallbackPolicy::Fail;
+        assert!(config.get_server_version(&target, &flags).is_err());
+
+        // Within the TTL the cached version stands
+        let entry = CacheEntry {
+            probed_at: now,
+            ..entry
+        };
+        fs::write(&cache_file, serde_json::to_string(&entry).unwrap()).unwrap();
+        let (version, source) = config.get_server_version(&target, &flags).unwrap();
+        assert_eq!(version, "v1.29.1");
+        assert_eq!(source, VersionSource::Cache);
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_cache_key_follows_cluster_not_context() {
+        let dev = ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &ConnectionFlags::default())
+            .unwrap();
+        let dev_admin =
+            ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &context_flags("dev-admin"))
+                .unwrap();
+        let prod =
+            ClusterTarget::from_kubeconfig(&parse(KUBECONFIG), &context_flags("prod")).unwrap();
+
+        assert_eq!(dev.cache_key(), dev_admin.cache_key());
+        assert_ne!(dev.cache_key(), prod.cache_key());
+        assert_eq!(dev.cache_key().len(), 64);
+
+        // Switching contexts or refreshing credentials must not change the key
+        let switched = KUBECONFIG
+            .replace("current-context: dev", "current-context: dev-admin")
+            .replace("token: abc", "token: refreshed");
+        let switched =
+            ClusterTarget::from_kubeconfig(&parse(&switched), &ConnectionFlags::default()).unwrap();
+        assert_eq!(dev.cache_key(), switched.cache_key());
+    }
+
+    // Minimal executable header for the platform the tests run on
+    fn fake_kubectl() -> Vec<u8> {
+        let mut bytes = vec![0u8; 64];
+        match detect_os().as_str() {
+            "darwin" => {
+                bytes[0..4].copy_from_slice(&[0xcf, 0xfa, 0xed, 0xfe]);
+                let cpu_type: u32 = if detect_cpu_arch() == "arm64" {
+                    0x0100_000c
+                } else {
+                    0x0100_0007
+                };
+                bytes[4..8].copy_from_slice(&cpu_type.to_le_bytes());
+            }
+            "windows" => bytes[0..2].copy_from_slice(b"MZ"),
+            _ => {
+                bytes[0..4].copy_from_slice(b"\x7fELF");
+                bytes[4] = 2;
+                bytes[5] = 1;
+                let machine: u16 = match detect_cpu_arch().as_str() {
+                    "386" => 3,
+                    "arm" => 40,
+                    "arm64" => 183,
+                    "ppc64le" => 21,
+                    "s390x" => 22,
+                    _ => 62,
+                };
+                bytes[18..20].copy_from_slice(&machine.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(b"A bunch of bytes");
+        bytes
+    }
+
+    fn mock_kubectl(server: &mut mockito::Server, version: &str, body: &[u8], checksum: &str) {
+        let path = format!(
+            "/release/{}/bin/{}/{}/kubectl",
+            version,
+            detect_os(),
+            detect_cpu_arch()
+        );
+        server
+            .mock("GET", path.as_str())
+            .with_status(200)
+            .with_body(body)
+            .create();
+        server
+            .mock("GET", format!("{}.sha256", path).as_str())
+            .with_status(200)
+            .with_body(checksum)
+            .create();
+    }
+
+    #[test]
+    fn test_download_kubectl() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let mut server = mockito::Server::new();
+        let test_file_content = fake_kubectl();
+        let checksum = format!("{:x}", Sha256::digest(&test_file_content));
+
+        // Test downloading a specific version
+        let version = "v1.23.0";
+        mock_kubectl(&mut server, version, &test_file_content, &checksum);
+
+        let mut config = test_config();
+        config.dl_url = server.url();
+
+        let result = config.download_kubectl(version);
+        assert!(result.is_ok(), "Test failed: result is {:?}", result);
+
+        let target_path = config.korrect_bin_path.join(format!("kubectl-{}", version));
+        assert!(target_path.exists());
+        assert_eq!(fs::read(&target_path).unwrap(), test_file_content);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&target_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_download_kubectl_resumes_across_installs() {
+        use std::io::BufRead;
+        use std::net::TcpListener;
+
+        let (temp_dir, _) = setup_temp_home();
+        let content = fake_kubectl();
+        let checksum = format!("{:x}", Sha256::digest(&content));
+        let version = "v1.23.1";
+        let half = content.len() / 2;
+
+        // Serves the checksum, then drops the connection halfway through the
+        // binary, as a killed korrect or a lost VPN would
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let cut_url = format!("http://{}", listener.local_addr().unwrap());
+        let (body, checksum_body) = (content.clone(), checksum.clone());
+        let cut = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                let mut reader = io::BufReader::new(&stream);
+                // Up to the blank line ending the headers
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                let (length, payload) = if request.contains(".sha256") {
+                    (checksum_body.len(), checksum_body.as_bytes())
+                } else {
+                    (body.len(), &body[..half])
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    length
+                )
+                .unwrap();
+                stream.write_all(payload).unwrap();
+            }
+        });
+
+        let mut config = test_config();
+        config.dl_url = cut_url;
+        config.download_options.max_retries = 0;
+        assert!(config.download_kubectl(version).is_err());
+        cut.join().unwrap();
+        let partial = config
+            .korrect_bin_path
+            .join(format!(".kubectl-{}.part", version));
+        assert_eq!(fs::read(&partial).unwrap(), &content[..half]);
+
+        // The next install only fetches the rest
+        let mut server = mockito::Server::new();
+        let path = format!(
+            "/release/{}/bin/{}/{}/kubectl",
+            version,
+            detect_os(),
+            detect_cpu_arch()
+        );
+        server
+            .mock("GET", format!("{}.sha256", path).as_str())
+            .with_status(200)
+            .with_body(&checksum)
+            .create();
+        let rest = server
+            .mock("GET", path.as_str())
+            .match_header("range", format!("bytes={}-", half).as_str())
+            .with_status(206)
+            .with_header(
+                "content-range",
+                &format!("bytes {}-{}/{}", half, content.len() - 1, content.len()),
+            )
+            .with_body(&content[half..])
+            .expect(1)
+            .create();
+        config.dl_url = server.url();
+        let installed = config.download_kubectl(version).unwrap();
+        rest.assert();
+        assert_eq!(fs::read(&installed).unwrap(), content);
+        assert!(!partial.exists());
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_download_kubectl_checksum_mismatch() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let mut server = mockito::Server::new();
+        let version = "v1.24.0";
+        let checksum = format!("{:x}", Sha256::digest(b"something else"));
+        mock_kubectl(&mut server, version, b"tampered bytes", &checksum);
+
+        let mut config = test_config();
+        config.dl_url = server.url();
+
+        let err = config.download_kubectl(version).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+
+        // Neither the target nor the temp file may be left behind
+        let leftovers: Vec<_> = fs::read_dir(&config.korrect_bin_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert!(leftovers.is_empty(), "leftover files: {:?}", leftovers);
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_download_kubectl_http_error() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(404)
+            .with_body("<html>Not Found</html>")
+            .create();
+
+        let mut config = test_config();
+        config.dl_url = server.url();
+
+        let err = config.download_kubectl("v9.99.0").unwrap_err();
+        assert!(format!("{:#}", err).contains("v9.99.0"), "{:#}", err);
+        match err.downcast_ref::<DownloadError>() {
+            Some(DownloadError::HttpStatus { url, status }) => {
+                assert_eq!(*status, StatusCode::NOT_FOUND);
+                assert!(url.contains("/release/v9.99.0/"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(!config.korrect_bin_path.join("kubectl-v9.99.0").exists());
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_download_kubectl_not_executable() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let mut server = mockito::Server::new();
+        let version = "v1.25.0";
+        let body = b"<html>Access denied by proxy</html>";
+        let checksum = format!("{:x}", Sha256::digest(body));
+        mock_kubectl(&mut server, version, body, &checksum);
+
+        let mut config = test_config();
+        config.dl_url = server.url();
+
+        let err = config.download_kubectl(version).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DownloadError>(),
+            Some(DownloadError::NotExecutable { .. })
+        ));
+        assert!(!config
+            .korrect_bin_path
+            .join(format!("kubectl-{}", version))
+            .exists());
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_get_current_stable_version() {
+        let (temp_dir, _) = setup_temp_home();
+
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let test_file_content = b"v1.2.3";
+
+        server
+            .mock("GET", "/release/stable.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body(test_file_content)
+            .create();
+
+        env::set_var("KORRECT_BASE_URL", url);
+        let config = test_config();
+        env::remove_var("KORRECT_BASE_URL");
+
+        let version = config.get_current_stable_version();
+        assert!(version.is_ok());
+        let version_str = version.unwrap();
+        assert!(version_str.starts_with('v'));
+        assert!(Regex::new(r"v\d+\.\d+\.\d+")
+            .unwrap()
+            .is_match(&version_str));
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_get_server_version_with_cache() {
+        let (temp_dir, _) = setup_temp_home();
+        let config = test_config();
+
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(&kubeconfig, KUBECONFIG).unwrap();
+        let kubeconfig = kubeconfig.to_str().unwrap();
+
+        // Create a cached version
+        let target = config.resolve_cluster_target(&kubeconfig_flags(kubeconfig));
+        write_cache_entry(&config, &target, "v1.23.0");
+
+        let version = server_version(&config, &kubeconfig_flags(kubeconfig)).unwrap();
+        assert_eq!(version, "v1.23.0");
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_resolve_kubectl_offline_when_cached() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        // Nothing listens here, so any network access would fail the test
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(&kubeconfig, KUBECONFIG).unwrap();
+        let kubeconfig = kubeconfig.to_str().unwrap();
+
+        let target = config.resolve_cluster_target(&kubeconfig_flags(kubeconfig));
+        write_cache_entry(&config, &target, "v1.29.4");
+        let installed = config.korrect_bin_path.join("kubectl-v1.29.4");
+        fs::write(&installed, fake_kubectl()).unwrap();
+
+        let resolution = config
+            .resolve_kubectl(&kubeconfig_flags(kubeconfig))
+            .unwrap();
+        assert_eq!(resolution.version, "v1.29.4");
+        assert_eq!(resolution.path, installed);
+        assert_eq!(resolution.source, VersionSource::Cache);
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_resolve_kubectl_records_selection_and_use() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        config.selection_policy = SelectionPolicy::ReuseCompatible;
+
+        let kubeconfig = temp_dir.path().join("kubeconfig");
+        fs::write(&kubeconfig, KUBECONFIG).unwrap();
+        let flags = kubeconfig_flags(kubeconfig.to_str().unwrap());
+
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.29.9");
+        fs::write(
+            config.korrect_bin_path.join("kubectl-v1.29.5"),
+            fake_kubectl(),
+        )
+        .unwrap();
+
+        let resolution = config.resolve_kubectl(&flags).unwrap();
+        assert_eq!(resolution.version, "v1.29.5");
+        assert_eq!(resolution.server_version.as_deref(), Some("v1.29.9"));
+
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.version.as_deref(), Some("v1.29.9"));
+        assert_eq!(entry.kubectl_version(), Some("v1.29.5"));
+        assert!(config.store().last_used("v1.29.5").is_some());
+
+        remove_temp_home(temp_dir);
+    }
+
+    const SKEW_WARNING: &str = "WARNING: version difference between client (1.29) and server (1.31) exceeds the supported minor version skew of +/-1";
+
+    /// Installs a shell script standing in for kubectl `version`.
+    fn script_kubectl(config: &KorrectShimConfig, version: &str, script: &str) {
+        let path = config.korrect_bin_path.join(format!("kubectl-{}", version));
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        crate::download::make_executable(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_skew_warning() {
+        assert_eq!(
+            parse_skew_warning(SKEW_WARNING).as_deref(),
+            Some("client 1.29, server 1.31")
+        );
+        assert_eq!(parse_skew_warning("Error from server (NotFound)"), None);
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        let args =
+            |args: &str| -> Vec<String> { args.split_whitespace().map(str::to_owned).collect() };
+        assert!(is_read_only(&args("get pods -o wide")));
+        assert!(is_read_only(&args(
+            "--context prod -n kube-system describe pod x"
+        )));
+        assert!(is_read_only(&args("get -f deployment.yaml")));
+        assert!(is_read_only(&args("logs web-0")));
+        assert!(!is_read_only(&args("get pods -w")));
+        assert!(!is_read_only(&args("logs --follow=true web-0")));
+        assert!(!is_read_only(&args("--context get delete pod x")));
+        assert!(!is_read_only(&args("apply -f deployment.yaml")));
+        assert!(!is_read_only(&args("-n")));
+    }
+
+    #[test]
+    fn test_skew_warning_invalidates_cache() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        config.watch_skew = true;
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.29.4");
+        script_kubectl(
+            &config,
+            "v1.29.4",
+            &format!("echo '{}' >&2; exit 3", SKEW_WARNING),
+        );
+
+        let args = vec![
+            "--kubeconfig".to_owned(),
+            flags.kubeconfig.clone().unwrap(),
+            "version".to_owned(),
+        ];
+        assert_eq!(config.run_kubectl(&args).unwrap(), 3);
+        assert!(read_cache_entry(&config.get_version_cache_file(&target)).is_none());
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_skew_warning_reruns_read_only_commands() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        config.watch_skew = true;
+        config.rerun_on_skew = true;
+
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/version")
+            .with_status(200)
+            .with_body(r#"{"gitVersion":"v1.31.0"}"#)
+            .create();
+        let flags = unreachable_kubeconfig(&temp_dir, &server.url());
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.29.4");
+        script_kubectl(&config, "v1.29.4", &format!("echo '{}' >&2", SKEW_WARNING));
+        script_kubectl(&config, "v1.31.0", "exit 7");
+
+        let args = vec![
+            "--kubeconfig".to_owned(),
+            flags.kubeconfig.clone().unwrap(),
+            "get".to_owned(),
+            "pods".to_owned(),
+        ];
+        assert_eq!(config.run_kubectl(&args).unwrap(), 7);
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.version.as_deref(), Some("v1.31.0"));
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_skew_warning_keeps_cache_for_pinned_version() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+        config.watch_skew = true;
+        config.rerun_on_skew = true;
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.31.0");
+        let mut pins = crate::pins::Pins::new();
+        pins.insert(
+            "dev".to_owned(),
+            Pin {
+                version: "v1.29.4".to_owned(),
+                spec: "1.29.4".to_owned(),
+                pinned_at: unix_now(),
+            },
+        );
+        crate::pins::write_pins(&config.korrect_config_path, &pins).unwrap();
+        script_kubectl(
+            &config,
+            "v1.29.4",
+            &format!("echo '{}' >&2; exit 3", SKEW_WARNING),
+        );
+        script_kubectl(&config, "v1.31.0", "exit 7");
+
+        let args = vec![
+            "--kubeconfig".to_owned(),
+            flags.kubeconfig.clone().unwrap(),
+            "get".to_owned(),
+            "pods".to_owned(),
+        ];
+        // Neither invalidated nor re-run with the cluster's kubectl
+        assert_eq!(config.run_kubectl(&args).unwrap(), 3);
+        let entry = read_cache_entry(&config.get_version_cache_file(&target)).unwrap();
+        assert_eq!(entry.version.as_deref(), Some("v1.31.0"));
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_pin_overrides_cache_and_probe() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.strict_skew = true;
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.30.1");
+        let mut pins = crate::pins::Pins::new();
+        pins.insert(
+            "dev".to_owned(),
+            Pin {
+                version: "v1.27.3".to_owned(),
+                spec: "1.27".to_owned(),
+                pinned_at: unix_now(),
+            },
+        );
+        crate::pins::write_pins(&config.korrect_config_path, &pins).unwrap();
+
+        // Pinned versions skip the skew check
+        let resolution = config.resolve(&flags).unwrap();
+        assert_eq!(resolution.version, "v1.27.3");
+        assert_eq!(resolution.source, VersionSource::Pin);
+        assert_eq!(resolution.server_version.as_deref(), Some("v1.30.1"));
+        assert_eq!(resolution.pin.as_ref().unwrap().spec, "1.27");
+
+        // The cache keeps describing the cluster, not the pin
+        config.record_selection(&resolution);
+        let entry = read_cache_entry(&resolution.cache_file).unwrap();
+        assert_eq!(entry.kubectl_version(), Some("v1.30.1"));
+
+        assert!(config.pin("prod").is_none());
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_project_file_overrides_cluster() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.30.1");
+        let project = temp_dir.path().join("infra");
+        config.project_dir = Some(project.join("scripts"));
+        fs::create_dir_all(config.project_dir.as_ref().unwrap()).unwrap();
+        fs::write(project.join(".kubectl-version"), "1.27\n").unwrap();
+
+        // A minor resolves to the newest installed patch without the network
+        fs::write(config.korrect_bin_path.join("kubectl-v1.27.3"), "").unwrap();
+        fs::write(config.korrect_bin_path.join("kubectl-v1.27.9"), "").unwrap();
+        let resolution = config.resolve(&flags).unwrap();
+        assert_eq!(resolution.version, "v1.27.9");
+        assert_eq!(resolution.source, VersionSource::ProjectFile);
+        assert_eq!(
+            resolution.version_file.as_ref().unwrap().path,
+            project.join(".kubectl-version")
+        );
+        assert_eq!(resolution.server_version.as_deref(), Some("v1.30.1"));
+
+        fs::write(project.join(".kubectl-version"), "1.26\n").unwrap();
+        let err = config.resolve(&flags).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains(".kubectl-version"),
+            "{:#}",
+            err
+        );
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_program_version() {
+        assert_eq!(program_version("kubectl-1.27").as_deref(), Some("1.27"));
+        assert_eq!(
+            program_version("/home/me/.korrect/bin/kubectl@v1.27.3").as_deref(),
+            Some("v1.27.3")
+        );
+        assert_eq!(program_version("kubectl"), None);
+        assert_eq!(program_version("kubectl-shim"), None);
+        assert_eq!(program_version("k"), None);
+    }
+
+    #[test]
+    fn test_requested_versions_take_precedence() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.dl_url = "http://127.0.0.1:9".to_owned();
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        write_cache_entry(&config, &target, "v1.30.1");
+        config.project_dir = Some(temp_dir.path().to_owned());
+        fs::write(temp_dir.path().join(".kubectl-version"), "v1.28.0\n").unwrap();
+        fs::write(config.korrect_bin_path.join("kubectl-v1.27.3"), "").unwrap();
+
+        config.env_version = Some("1.27".to_owned());
+        let resolution = config.resolve(&flags).unwrap();
+        assert_eq!(resolution.version, "v1.27.3");
+        assert_eq!(resolution.source, VersionSource::Env);
+
+        config.program_version = Some("v1.29.2".to_owned());
+        let resolution = config.resolve(&flags).unwrap();
+        assert_eq!(resolution.version, "v1.29.2");
+        assert_eq!(resolution.source, VersionSource::ProgramName);
+        assert!(!resolution.installed);
+
+        // Missing versions are only fetched with auto-download on
+        config.auto_download = false;
+        let err = config.resolve_kubectl(&flags).unwrap_err();
+        assert!(
+            err.to_string().contains("korrect install v1.29.2"),
+            "{}",
+            err
+        );
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_cache_entry_expiry() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.cache_ttl = Duration::from_secs(3600);
+        let now = unix_now();
+
+        let entry = CacheEntry {
+            version: Some("v1.29.0".to_owned()),
+            probed_at: now - 60,
+            ..CacheEntry::default()
+        };
+        assert!(!config.is_expired(&entry, now));
+        assert!(config.is_expired(&entry, now + 3600));
+
+        // A failed re-validation waits out its backoff
+        let failed = CacheEntry {
+            failures: 1,
+            failed_at: Some(now + 3600),
+            ..entry.clone()
+        };
+        assert!(!config.is_expired(&failed, now + 3600));
+
+        config.cache_ttl = Duration::ZERO;
+        assert!(!config.is_expired(&entry, now + 365 * 86_400));
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_expired_entry_revalidates_in_background() {
+        let (temp_dir, _) = setup_temp_home();
+        let mut config = test_config();
+        config.cache_ttl = Duration::from_secs(60);
+
+        let flags = unreachable_kubeconfig(&temp_dir, "http://127.0.0.1:9");
+        let target = config.resolve_cluster_target(&flags);
+        let cache_file = config.get_version_cache_file(&target);
+        let entry = CacheEntry {
+            context: target.context.clone(),
+            server: target.server.clone(),
+            version: Some("v1.29.0".to_owned()),
+            probed_at: unix_now() - 120,
+            ..CacheEntry::default()
+        };
+        fs::write(&cache_file, serde_json::to_string(&entry).unwrap()).unwrap();
+
+        // Stands in for kubectl-shim, recording how it was started
+        let marker = temp_dir.path().join("revalidated");
+        let shim = config.korrect_bin_path.join("kubectl-shim");
+        fs::write(
+            &shim,
+            format!(
+                "#!/bin/sh\necho \"$@ ${}\" > {}.tmp && mv {}.tmp {}\n",
+                REVALIDATE_ENV,
+                marker.display(),
+                marker.display(),
+                marker.display()
+            ),
+        )
+        .unwrap();
+        crate::download::make_executable(&shim).unwrap();
+
+        // The stale version is used right away
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.29.0");
+        let lock = cache_file.with_extension("lock");
+        for _ in 0..50 {
+            if marker.exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(
+            fs::read_to_string(&marker).unwrap().trim(),
+            format!(
+                "--kubeconfig={} {}",
+                flags.kubeconfig.as_ref().unwrap(),
+                lock.display()
+            )
+        );
+
+        // The lock is still held, so nothing else is started
+        fs::remove_file(&marker).unwrap();
+        assert_eq!(server_version(&config, &flags).unwrap(), "v1.29.0");
+        thread::sleep(Duration::from_millis(300));
+        assert!(!marker.exists());
+
+        remove_temp_home(temp_dir);
+    }
+
+    #[test]
+    fn test_installed_versions() {
+        let (temp_dir, _) = setup_temp_home();
+        let config = test_config();
+
+        for name in [
+            "kubectl-v1.9.0",
+            "kubectl-v1.30.1",
+            "kubectl-v1.29.10",
+            "kubectl-shim",
+            "kubectl",
+            "k",
+        ] {
+            fs::write(config.korrect_bin_path.join(name), "").unwrap();
+        }
+
+        assert_eq!(
+            config.installed_versions(),
+            vec!["v1.9.0", "v1.29.10", "v1.30.1"]
+        );
+        assert_eq!(
+            config.get_probe_kubectl().unwrap(),
+            config.korrect_bin_path.join("kubectl-v1.30.1")
+        );
+
+        remove_temp_home(temp_dir);
+    }
+}
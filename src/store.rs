@@ -0,0 +1,578 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::cache::unix_now;
+use crate::download::{
+    check_status, detect_cpu_arch, detect_os, download_file_with_progress, make_executable,
+    sha256_file, validate_executable, DownloadError, DownloadOptions,
+};
+use crate::version::KubeVersion;
+
+/// How long a download temp file may sit untouched before it is taken to be
+/// left behind by an interrupted install.
+const ABANDONED_TEMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How long a partial download is kept for a later install to resume.
+const ABANDONED_PARTIAL_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Whether an installed kubectl still matches the checksum it was verified
+/// against when it was downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChecksumStatus {
+    Verified,
+    Mismatch,
+    /// Installed before korrect recorded checksums, or copied in by hand
+    Unknown,
+}
+
+impl fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumStatus::Verified => write!(f, "verified"),
+            ChecksumStatus::Mismatch => write!(f, "mismatch"),
+            ChecksumStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The kubectl binaries korrect manages, installed as `kubectl-<version>` in
+/// `bin_path` from the release server at `dl_url`.
+#[derive(Debug, Clone)]
+pub struct KubectlStore {
+    pub bin_path: PathBuf,
+    pub dl_url: String,
+    pub os: String,
+    pub cpu_arch: String,
+    pub download_options: DownloadOptions,
+}
+
+impl KubectlStore {
+    pub fn new(bin_path: PathBuf, dl_url: String) -> Self {
+        Self {
+            bin_path,
+            dl_url,
+            os: detect_os(),
+            cpu_arch: detect_cpu_arch(),
+            download_options: DownloadOptions::from_env(),
+        }
+    }
+
+    pub fn kubectl_path(&self, version: &str) -> PathBuf {
+        self.bin_path.join(format!("kubectl-{}", version))
+    }
+
+    /// Sidecar file holding metadata about an installed version, hidden so
+    /// that it never shows up as a kubectl.
+    fn sidecar_path(&self, version: &str, kind: &str) -> PathBuf {
+        self.bin_path.join(format!(".kubectl-{}.{}", version, kind))
+    }
+
+    /// Remembers that `version` was just run. Failures are ignored, this only
+    /// feeds `korrect list`.
+    pub fn record_use(&self, version: &str) {
+        fs::write(
+            self.sidecar_path(version, "last-used"),
+            unix_now().to_string(),
+        )
+        .ok();
+    }
+
+    /// When `version` was last run, in seconds since the Unix epoch.
+    pub fn last_used(&self, version: &str) -> Option<u64> {
+        fs::read_to_string(self.sidecar_path(version, "last-used"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Re-hashes an installed kubectl and compares it with the checksum
+    /// recorded at install time.
+    pub fn verify(&self, version: &str) -> Result<ChecksumStatus> {
+        let Ok(expected) = fs::read_to_string(self.sidecar_path(version, "sha256")) else {
+            return Ok(ChecksumStatus::Unknown);
+        };
+        let actual = sha256_file(&self.kubectl_path(version))?;
+        Ok(if actual == expected.trim() {
+            ChecksumStatus::Verified
+        } else {
+            ChecksumStatus::Mismatch
+        })
+    }
+
+    /// Lists the kubectl versions present in the bin directory, oldest first,
+    /// as tags. Only regular files named `kubectl-v<version>` count: versioned
+    /// shim links such as `kubectl-1.27.3` would otherwise be run as kubectl
+    /// and loop back into the shim.
+    pub fn installed_versions(&self) -> Vec<String> {
+        let mut versions: Vec<KubeVersion> = fs::read_dir(&self.bin_path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        let version = name.strip_prefix("kubectl-")?.parse::<KubeVersion>().ok()?;
+                        (name == format!("kubectl-{}", version.tag())
+                            && self.is_kubectl_binary(&entry.path()))
+                        .then_some(version)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        versions.sort();
+        versions.iter().map(KubeVersion::tag).collect()
+    }
+
+    /// Whether kubectl `version` is installed as a real binary rather than a
+    /// link back to the shim.
+    pub fn is_installed(&self, version: &str) -> bool {
+        self.is_kubectl_binary(&self.kubectl_path(version))
+    }
+
+    fn is_kubectl_binary(&self, path: &Path) -> bool {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return false;
+        };
+        if !metadata.is_file() {
+            return false;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // A hard link to the shim is the shim
+            if let Ok(shim) = fs::metadata(self.bin_path.join("kubectl-shim")) {
+                if shim.dev() == metadata.dev() && shim.ino() == metadata.ino() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Reads a release marker such as `stable` or `latest-1.31` from
+    /// `release/<marker>.txt`.
+    pub fn marker_version(&self, marker: &str) -> Result<KubeVersion> {
+        let url = format!("{}/release/{}.txt", self.dl_url, marker);
+        let client = self.download_options.client()?;
+        let resp = check_status(client.get(&url).send()?, &url)?;
+        let body = resp.text()?;
+        body.trim()
+            .parse()
+            .with_context(|| format!("{} does not hold a kubectl version", url))
+    }
+
+    /// Resolves an install spec to the kubectl release it names: an exact
+    /// version (`v1.29.3`, `1.32.0-rc.1`), a minor version for its latest
+    /// patch (`1.29`), or a channel (`stable`, `latest`, `stable-1.28`,
+    /// `latest-1.31`). Channels only resolve to pre-releases when
+    /// `allow_prerelease` is set.
+    pub fn resolve_spec(&self, spec: &str, allow_prerelease: bool) -> Result<String> {
+        let spec = spec.trim();
+        let marker = if is_channel(spec) {
+            spec.to_owned()
+        } else if let Some((major, minor)) = parse_minor(spec) {
+            format!("stable-{}.{}", major, minor)
+        } else {
+            let version: KubeVersion = spec
+                .parse()
+                .map_err(|e| anyhow!("invalid version spec {}: {}", spec, e))?;
+            return Ok(version.tag());
+        };
+
+        let version = self.marker_version(&marker)?;
+        if version.is_prerelease() && !allow_prerelease {
+            return Err(anyhow!(
+                "{} resolves to the pre-release {}, request it by its exact version to install it",
+                spec,
+                version.tag()
+            ));
+        }
+        Ok(version.tag())
+    }
+
+    /// The newest installed release matching an exact version or a
+    /// `<major>.<minor>` spec, letting such specs resolve without the network.
+    pub fn installed_match(&self, spec: &str) -> Option<String> {
+        let spec = spec.trim();
+        if let Some((major, minor)) = parse_minor(spec) {
+            return self.installed_versions().into_iter().rev().find(|version| {
+                version.parse::<KubeVersion>().is_ok_and(|version| {
+                    version.major == major && version.minor == minor && !version.is_prerelease()
+                })
+            });
+        }
+        let tag = spec.parse::<KubeVersion>().ok()?.tag();
+        self.is_installed(&tag).then_some(tag)
+    }
+
+    fn get_kubectl_checksum(&self, url: &str) -> Result<String> {
+        let checksum_url = format!("{}.sha256", url);
+        let client = self.download_options.client()?;
+        let resp = check_status(client.get(&checksum_url).send()?, &checksum_url)?;
+        let body = resp.text()?;
+        // The published file holds the bare hex digest, but tolerate the
+        // `<digest>  <filename>` layout emitted by sha256sum as well.
+        let checksum = body
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid checksum received from {}.sha256", url));
+        }
+        Ok(checksum)
+    }
+
+    /// Downloads and verifies kubectl `version` unless it is already installed.
+    pub fn install(&self, version: &str) -> Result<PathBuf> {
+        let target_path = self.kubectl_path(version);
+
+        if self.is_installed(version) {
+            return Ok(target_path);
+        }
+        fs::create_dir_all(&self.bin_path)?;
+        self.sweep_abandoned_downloads();
+
+        let url = format!(
+            "{}/release/{}/bin/{}/{}/kubectl",
+            self.dl_url, version, self.os, self.cpu_arch
+        );
+
+        let expected = self
+            .get_kubectl_checksum(&url)
+            .with_context(|| format!("Failed to fetch checksum for kubectl {}", version))?;
+
+        // Stream into a partial file next to the target so that the final
+        // rename is atomic. Its name is fixed so that a download cut short,
+        // even by killing korrect, resumes from where it stopped next time.
+        let partial_path = self.sidecar_path(version, "part");
+        let Some(mut partial) = self.open_partial(version)? else {
+            return Ok(target_path);
+        };
+
+        if let Err(e) = download_file_with_progress(&url, &mut partial, &self.download_options) {
+            if partial.metadata().is_ok_and(|metadata| metadata.len() == 0) {
+                fs::remove_file(&partial_path).ok();
+            }
+            return Err(e.context(format!("Failed to download kubectl {}", version)));
+        }
+
+        // Data that fails either check cannot be resumed from
+        let actual = sha256_file(&partial_path)?;
+        if actual != expected {
+            fs::remove_file(&partial_path).ok();
+            return Err(anyhow!(
+                "Checksum mismatch for kubectl {}: expected {}, got {}. The download has been removed.",
+                version,
+                expected,
+                actual
+            ));
+        }
+
+        if let Err(reason) = validate_executable(&partial_path, &self.os, &self.cpu_arch) {
+            fs::remove_file(&partial_path).ok();
+            return Err(anyhow!(DownloadError::NotExecutable {
+                url: url.clone(),
+                reason
+            })
+            .context(format!("Refusing to install kubectl {}", version)));
+        }
+
+        make_executable(&partial_path)?;
+        fs::rename(&partial_path, &target_path)
+            .with_context(|| format!("Failed to install {}", target_path.display()))?;
+        fs::write(self.sidecar_path(version, "sha256"), &expected).ok();
+
+        Ok(target_path)
+    }
+
+    /// Opens the partial download of `version` for appending, locked against
+    /// other korrect processes installing it at the same time. Returns `None`
+    /// when one of them finished installing it while this one waited.
+    fn open_partial(&self, version: &str) -> Result<Option<File>> {
+        let path = self.sidecar_path(version, "part");
+        loop {
+            let file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                use std::os::unix::io::AsRawFd;
+                // SAFETY: flock only reads the descriptor, which outlives the call
+                unsafe {
+                    libc::flock(file.as_raw_fd(), libc::LOCK_EX);
+                }
+                if self.is_installed(version) {
+                    return Ok(None);
+                }
+                // The holder of the lock renamed or removed the file, so this
+                // descriptor no longer refers to the partial download
+                let current = fs::metadata(&path).ok();
+                let opened = file.metadata()?;
+                if current.is_none_or(|current| current.ino() != opened.ino()) {
+                    continue;
+                }
+            }
+            return Ok(Some(file));
+        }
+    }
+
+    /// Removes downloads nothing will finish: temp files of earlier releases,
+    /// whose random names cannot be resumed, and partial downloads of versions
+    /// that were never installed after all.
+    fn sweep_abandoned_downloads(&self) {
+        let Ok(entries) = fs::read_dir(&self.bin_path) else {
+            return;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(".kubectl-") {
+                continue;
+            }
+            let abandoned_after = if name.ends_with(".tmp") {
+                ABANDONED_TEMP_AGE
+            } else if name.ends_with(".part") {
+                ABANDONED_PARTIAL_AGE
+            } else {
+                continue;
+            };
+            let idle = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok());
+            if idle.is_some_and(|idle| idle > abandoned_after) {
+                fs::remove_file(entry.path()).ok();
+            }
+        }
+    }
+
+    /// Removes an installed kubectl, accepting the version with or without
+    /// its `v` prefix.
+    pub fn uninstall(&self, version: &str) -> Result<PathBuf> {
+        let version: KubeVersion = version
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("invalid version {}: {}", version, e))?;
+        let path = self.kubectl_path(&version.tag());
+        if !self.is_installed(&version.tag()) {
+            return Err(anyhow!("kubectl {} is not installed", version.tag()));
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        for kind in ["sha256", "last-used"] {
+            fs::remove_file(self.sidecar_path(&version.tag(), kind)).ok();
+        }
+        Ok(path)
+    }
+}
+
+/// Whether `spec` names a release channel: `stable`, `latest`, or either
+/// followed by `-<major>.<minor>`.
+fn is_channel(spec: &str) -> bool {
+    ["stable", "latest"].iter().any(|channel| {
+        spec.strip_prefix(channel).is_some_and(|rest| {
+            rest.is_empty() || rest.strip_prefix('-').and_then(parse_minor).is_some()
+        })
+    })
+}
+
+/// Parses a `<major>.<minor>` spec, with an optional `v` prefix.
+fn parse_minor(spec: &str) -> Option<(u64, u64)> {
+    let spec = spec.strip_prefix('v').unwrap_or(spec);
+    let (major, minor) = spec.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[cfg(test)]
+mod store_tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn store(temp_dir: &TempDir, dl_url: String) -> KubectlStore {
+        KubectlStore::new(temp_dir.path().join("bin"), dl_url)
+    }
+
+    fn mock_marker(server: &mut mockito::Server, marker: &str, version: &str) {
+        server
+            .mock("GET", format!("/release/{}.txt", marker).as_str())
+            .with_status(200)
+            .with_body(format!("{}\n", version))
+            .create();
+    }
+
+    #[test]
+    fn test_resolve_spec() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut server = mockito::Server::new();
+        mock_marker(&mut server, "stable", "v1.31.2");
+        mock_marker(&mut server, "stable-1.28", "v1.28.15");
+        mock_marker(&mut server, "stable-1.29", "v1.29.10");
+        mock_marker(&mut server, "latest-1.30", "v1.30.6");
+        let store = store(&temp_dir, server.url());
+
+        assert_eq!(store.resolve_spec("v1.29.3", false).unwrap(), "v1.29.3");
+        assert_eq!(store.resolve_spec("1.29.3", false).unwrap(), "v1.29.3");
+        assert_eq!(store.resolve_spec("1.29", false).unwrap(), "v1.29.10");
+        assert_eq!(store.resolve_spec("v1.29", false).unwrap(), "v1.29.10");
+        assert_eq!(store.resolve_spec("stable", false).unwrap(), "v1.31.2");
+        assert_eq!(
+            store.resolve_spec("stable-1.28", false).unwrap(),
+            "v1.28.15"
+        );
+        assert_eq!(store.resolve_spec("latest-1.30", false).unwrap(), "v1.30.6");
+        assert!(store.resolve_spec("stable-1", false).is_err());
+        assert!(store.resolve_spec("newest", false).is_err());
+    }
+
+    #[test]
+    fn test_resolve_spec_prerelease() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut server = mockito::Server::new();
+        mock_marker(&mut server, "latest", "v1.32.0-rc.1");
+        let store = store(&temp_dir, server.url());
+
+        // Named explicitly
+        assert_eq!(
+            store.resolve_spec("v1.32.0-rc.1", false).unwrap(),
+            "v1.32.0-rc.1"
+        );
+        // Reached through a channel
+        let err = store.resolve_spec("latest", false).unwrap_err();
+        assert!(err.to_string().contains("pre-release"), "{}", err);
+        assert_eq!(store.resolve_spec("latest", true).unwrap(), "v1.32.0-rc.1");
+    }
+
+    #[test]
+    fn test_installed_match() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir, "http://127.0.0.1:9".to_owned());
+        fs::create_dir_all(&store.bin_path).unwrap();
+        for version in ["v1.27.3", "v1.27.16", "v1.28.0-rc.0"] {
+            fs::write(store.kubectl_path(version), "").unwrap();
+        }
+
+        assert_eq!(store.installed_match("1.27").as_deref(), Some("v1.27.16"));
+        assert_eq!(store.installed_match("1.27.3").as_deref(), Some("v1.27.3"));
+        assert_eq!(store.installed_match("1.28"), None);
+        assert_eq!(store.installed_match("v1.29.0"), None);
+        assert_eq!(store.installed_match("stable"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_installed_versions_skip_shim_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir, "http://127.0.0.1:9".to_owned());
+        fs::create_dir_all(&store.bin_path).unwrap();
+        let shim = store.bin_path.join("kubectl-shim");
+        fs::write(&shim, "shim").unwrap();
+        fs::write(store.kubectl_path("v1.27.3"), "kubectl").unwrap();
+        // Versioned shim names, linked and hard linked, and a non-tag name
+        std::os::unix::fs::symlink(&shim, store.bin_path.join("kubectl-1.28.0")).unwrap();
+        std::os::unix::fs::symlink(&shim, store.kubectl_path("v1.29.0")).unwrap();
+        fs::hard_link(&shim, store.kubectl_path("v1.30.0")).unwrap();
+        fs::write(store.bin_path.join("kubectl-1.31.0"), "kubectl").unwrap();
+
+        assert_eq!(store.installed_versions(), vec!["v1.27.3"]);
+        assert!(store.is_installed("v1.27.3"));
+        assert!(!store.is_installed("v1.29.0"));
+        assert!(!store.is_installed("v1.30.0"));
+        assert_eq!(store.installed_match("1.29"), None);
+        assert_eq!(store.installed_match("v1.30.0"), None);
+        assert!(store.uninstall("v1.29.0").is_err());
+    }
+
+    #[test]
+    fn test_sweep_abandoned_downloads() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir, "http://127.0.0.1:9".to_owned());
+        fs::create_dir_all(&store.bin_path).unwrap();
+        let touch = |name: &str, age: Duration| {
+            let path = store.bin_path.join(name);
+            fs::write(&path, "partial").unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(std::time::SystemTime::now() - age)
+                .unwrap();
+        };
+        let day = Duration::from_secs(24 * 60 * 60);
+        touch(".kubectl-v1.27.3.a1b2c3.tmp", day);
+        touch(".kubectl-v1.27.4.d4e5f6.tmp", Duration::ZERO);
+        touch(".kubectl-v1.28.0.part", day * 30);
+        touch(".kubectl-v1.29.0.part", day);
+        touch(".kubectl-v1.29.0.sha256", day * 30);
+
+        store.sweep_abandoned_downloads();
+        let mut left: Vec<String> = fs::read_dir(&store.bin_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec![
+                ".kubectl-v1.27.4.d4e5f6.tmp",
+                ".kubectl-v1.29.0.part",
+                ".kubectl-v1.29.0.sha256"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uninstall() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir, "http://127.0.0.1:9".to_owned());
+        fs::create_dir_all(&store.bin_path).unwrap();
+        fs::write(store.kubectl_path("v1.29.3"), "").unwrap();
+        fs::write(store.kubectl_path("v1.30.1"), "").unwrap();
+
+        assert_eq!(
+            store.uninstall("1.29.3").unwrap(),
+            store.kubectl_path("v1.29.3")
+        );
+        assert_eq!(store.installed_versions(), vec!["v1.30.1"]);
+        assert!(store.uninstall("v1.29.3").is_err());
+        assert!(store.uninstall("kubectl").is_err());
+    }
+
+    #[test]
+    fn test_usage_and_checksum_sidecars() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = store(&temp_dir, "http://127.0.0.1:9".to_owned());
+        fs::create_dir_all(&store.bin_path).unwrap();
+        let path = store.kubectl_path("v1.29.3");
+        fs::write(&path, "kubectl").unwrap();
+
+        assert_eq!(store.verify("v1.29.3").unwrap(), ChecksumStatus::Unknown);
+        fs::write(
+            store.sidecar_path("v1.29.3", "sha256"),
+            sha256_file(&path).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(store.verify("v1.29.3").unwrap(), ChecksumStatus::Verified);
+        fs::write(&path, "tampered").unwrap();
+        assert_eq!(store.verify("v1.29.3").unwrap(), ChecksumStatus::Mismatch);
+
+        assert_eq!(store.last_used("v1.29.3"), None);
+        store.record_use("v1.29.3");
+        assert!(store.last_used("v1.29.3").is_some());
+
+        // Sidecars never count as installed versions and leave with the binary
+        assert_eq!(store.installed_versions(), vec!["v1.29.3"]);
+        store.uninstall("v1.29.3").unwrap();
+        assert_eq!(fs::read_dir(&store.bin_path).unwrap().count(), 0);
+    }
+}
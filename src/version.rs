@@ -0,0 +1,371 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A Kubernetes version such as `v1.29.3`, `v1.32.0-rc.1`,
+/// `v1.29.3-eks-adc7111` or `v1.30.2+k3s1`.
+///
+/// Managed distributions append their own suffix to the upstream version.
+/// Those suffixes are kept as build metadata rather than treated as
+/// pre-releases, so `v1.29.3-eks-adc7111` still maps to kubectl `v1.29.3`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KubeVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Upstream pre-release such as `rc.1` or `alpha.2`
+    pub pre: Option<String>,
+    /// Build or distribution metadata such as `k3s1` or `eks-adc7111`
+    pub build: Option<String>,
+}
+
+/// Managed Kubernetes distributions recognised from version metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Distro {
+    Eks,
+    Gke,
+    Aks,
+    K3s,
+    Rke2,
+    OpenShift,
+}
+
+impl fmt::Display for Distro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Distro::Eks => write!(f, "EKS"),
+            Distro::Gke => write!(f, "GKE"),
+            Distro::Aks => write!(f, "AKS"),
+            Distro::K3s => write!(f, "k3s"),
+            Distro::Rke2 => write!(f, "RKE2"),
+            Distro::OpenShift => write!(f, "OpenShift"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseVersionError(String);
+
+impl fmt::Display for ParseVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid Kubernetes version: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseVersionError {}
+
+impl KubeVersion {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: None,
+            build: None,
+        }
+    }
+
+    /// The upstream release this version is built from, without pre-release
+    /// or distribution metadata.
+    pub fn release(&self) -> Self {
+        Self::new(self.major, self.minor, self.patch)
+    }
+
+    /// The name of the upstream kubectl release, e.g. `v1.32.0-rc.1`.
+    pub fn tag(&self) -> String {
+        match &self.pre {
+            Some(pre) => format!("v{}.{}.{}-{}", self.major, self.minor, self.patch, pre),
+            None => format!("v{}.{}.{}", self.major, self.minor, self.patch),
+        }
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some()
+    }
+
+    /// The managed distribution this version was reported by, if recognised.
+    /// OpenShift is recognised by its bare commit hash metadata (`+b49f9d1`).
+    pub fn distro(&self) -> Option<Distro> {
+        let build = self.build.as_deref()?.to_ascii_lowercase();
+        if build.starts_with("eks") {
+            Some(Distro::Eks)
+        } else if build.starts_with("gke") {
+            Some(Distro::Gke)
+        } else if build.starts_with("aks") {
+            Some(Distro::Aks)
+        } else if build.contains("rke2") {
+            Some(Distro::Rke2)
+        } else if build.contains("k3s") {
+            Some(Distro::K3s)
+        } else if build.len() >= 7 && build.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(Distro::OpenShift)
+        } else {
+            None
+        }
+    }
+
+    /// How many minor versions `self` is ahead of `other`, or `None` when the
+    /// major versions differ and skew is meaningless.
+    pub fn minor_skew(&self, other: &Self) -> Option<i64> {
+        (self.major == other.major).then(|| self.minor as i64 - other.minor as i64)
+    }
+
+    /// Whether a kubectl of version `self` is supported against a server of
+    /// version `server`, i.e. within `max_skew` minor versions either way.
+    pub fn within_skew(&self, server: &Self, max_skew: u64) -> bool {
+        self.minor_skew(server)
+            .is_some_and(|skew| skew.unsigned_abs() <= max_skew)
+    }
+
+    /// Finds the first `vX.Y.Z` version embedded in `text`, as found in the
+    /// output of tools that decorate the version.
+    pub fn find(text: &str) -> Option<Self> {
+        text.match_indices('v').find_map(|(start, _)| {
+            let candidate = &text[start..];
+            let end = candidate
+                .find(|c: char| c.is_whitespace() || c == '"' || c == ',')
+                .unwrap_or(candidate.len());
+            candidate[..end].parse().ok().or_else(|| {
+                // Fall back to the bare X.Y.Z core when the suffix is garbage
+                let core: String = candidate[1..]
+                    .chars()
+                    .take_while(|c| c.is_ascii_digit() || *c == '.')
+                    .collect();
+                format!("v{}", core.trim_end_matches('.')).parse().ok()
+            })
+        })
+    }
+}
+
+impl FromStr for KubeVersion {
+    type Err = ParseVersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseVersionError(s.to_owned());
+        let version = s.trim();
+        let version = version
+            .strip_prefix('v')
+            .or_else(|| version.strip_prefix('V'))
+            .unwrap_or(version);
+
+        let (version, build) = match version.split_once('+') {
+            Some((version, build)) => (version, Some(build)),
+            None => (version, None),
+        };
+        let (core, suffix) = match version.split_once('-') {
+            Some((core, suffix)) => (core, Some(suffix)),
+            None => (version, None),
+        };
+
+        let mut parts = core.split('.');
+        let mut number = || -> Result<u64, ParseVersionError> {
+            let part = parts.next().ok_or_else(err)?;
+            if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+                return Err(err());
+            }
+            part.parse().map_err(|_| err())
+        };
+        let (major, minor, patch) = (number()?, number()?, number()?);
+        if parts.next().is_some() {
+            return Err(err());
+        }
+
+        // Distributions such as EKS and GKE use `-` for their suffix, which
+        // would otherwise read as a pre-release
+        let (pre, build) = match suffix {
+            Some(suffix) if is_upstream_prerelease(suffix) => {
+                (Some(suffix), build.map(str::to_owned))
+            }
+            Some(suffix) => match build {
+                Some(build) => (None, Some(format!("{}+{}", suffix, build))),
+                None => (None, Some(suffix.to_owned())),
+            },
+            None => (None, build.map(str::to_owned)),
+        };
+        let valid = |value: &str| {
+            !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+        };
+        if pre.is_some_and(|pre| !valid(pre)) || build.as_deref().is_some_and(|b| !valid(b)) {
+            return Err(err());
+        }
+
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            pre: pre.map(str::to_owned),
+            build,
+        })
+    }
+}
+
+fn is_upstream_prerelease(suffix: &str) -> bool {
+    ["alpha", "beta", "rc"]
+        .iter()
+        .any(|kind| suffix.starts_with(kind))
+}
+
+impl fmt::Display for KubeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tag())?;
+        if let Some(build) = &self.build {
+            write!(f, "+{}", build)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for KubeVersion {
+    /// Orders by semver precedence, with releases after their pre-releases.
+    /// Build metadata only breaks ties so that the order stays total.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_prerelease(a, b),
+            })
+            .then_with(|| self.build.cmp(&other.build))
+    }
+}
+
+impl PartialOrd for KubeVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn compare_prerelease(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => {
+                let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => a.cmp(b),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod version_tests {
+    use super::*;
+
+    fn v(version: &str) -> KubeVersion {
+        version.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_upstream() {
+        assert_eq!(v("v1.29.3"), KubeVersion::new(1, 29, 3));
+        assert_eq!(v("1.29.3"), KubeVersion::new(1, 29, 3));
+
+        let rc = v("v1.32.0-rc.1");
+        assert_eq!(rc.pre.as_deref(), Some("rc.1"));
+        assert!(rc.is_prerelease());
+        assert_eq!(rc.tag(), "v1.32.0-rc.1");
+        assert_eq!(rc.distro(), None);
+
+        assert!("v1.29".parse::<KubeVersion>().is_err());
+        assert!("v1.29.3.4".parse::<KubeVersion>().is_err());
+        assert!("version1.2.3".parse::<KubeVersion>().is_err());
+        assert!("v1.x.3".parse::<KubeVersion>().is_err());
+        assert!("v1.2.3-".parse::<KubeVersion>().is_err());
+    }
+
+    #[test]
+    fn test_parse_distros() {
+        let cases = [
+            ("v1.29.3-eks-adc7111", Some(Distro::Eks), "eks-adc7111"),
+            ("v1.28.9-gke.1000000", Some(Distro::Gke), "gke.1000000"),
+            ("v1.29.2-aks.1", Some(Distro::Aks), "aks.1"),
+            ("v1.30.2+k3s1", Some(Distro::K3s), "k3s1"),
+            ("v1.27.16+rke2r1", Some(Distro::Rke2), "rke2r1"),
+            ("v1.27.6+b49f9d1", Some(Distro::OpenShift), "b49f9d1"),
+            ("v1.29.1+custom", None, "custom"),
+        ];
+        for (input, distro, build) in cases {
+            let version = v(input);
+            assert_eq!(version.distro(), distro, "{}", input);
+            assert_eq!(version.build.as_deref(), Some(build), "{}", input);
+            assert!(!version.is_prerelease(), "{}", input);
+            assert_eq!(
+                version.release(),
+                KubeVersion::new(1, version.minor, version.patch)
+            );
+        }
+        assert_eq!(v("v1.29.3-eks-adc7111").tag(), "v1.29.3");
+        assert_eq!(v("v1.30.2+k3s1").to_string(), "v1.30.2+k3s1");
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut versions = [
+            v("v1.10.0"),
+            v("v1.9.12"),
+            v("v1.10.0-rc.1"),
+            v("v1.10.0-beta.2"),
+            v("v1.10.0-rc.10"),
+            v("v1.10.0-rc.2"),
+            v("v1.10.0-alpha.1"),
+        ];
+        versions.sort();
+        let tags: Vec<String> = versions.iter().map(KubeVersion::tag).collect();
+        assert_eq!(
+            tags,
+            vec![
+                "v1.9.12",
+                "v1.10.0-alpha.1",
+                "v1.10.0-beta.2",
+                "v1.10.0-rc.1",
+                "v1.10.0-rc.2",
+                "v1.10.0-rc.10",
+                "v1.10.0",
+            ]
+        );
+        assert_ne!(v("v1.29.3+k3s1").cmp(&v("v1.29.3+k3s2")), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_skew() {
+        let server = v("v1.29.3-eks-adc7111");
+        assert_eq!(v("v1.31.0").minor_skew(&server), Some(2));
+        assert_eq!(v("v1.28.9").minor_skew(&server), Some(-1));
+        assert!(v("v1.30.1").within_skew(&server, 1));
+        assert!(v("v1.28.0").within_skew(&server, 1));
+        assert!(!v("v1.31.0").within_skew(&server, 1));
+        assert_eq!(v("v2.29.0").minor_skew(&server), None);
+        assert!(!v("v2.29.0").within_skew(&server, 1));
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(
+            KubeVersion::find("somethingv1.2.3-alpha"),
+            Some(v("v1.2.3-alpha"))
+        );
+        assert_eq!(
+            KubeVersion::find(r#"{"gitVersion":"v1.30.2+k3s1","x":1}"#),
+            Some(v("v1.30.2+k3s1"))
+        );
+        assert_eq!(KubeVersion::find("v1.2.3-~weird"), Some(v("v1.2.3")));
+        assert_eq!(KubeVersion::find("version1.2.3"), None);
+        assert_eq!(KubeVersion::find("invalid"), None);
+    }
+}